@@ -0,0 +1,226 @@
+use std::f64::consts::TAU;
+
+use iced::widget::{text, toggler, Column, Container, Rule};
+use iced::Element;
+use serde::{Deserialize, Serialize};
+
+use crate::export::AnimationFormat;
+use crate::gui::lpicklist::LPickList;
+use crate::gui::numeric_input::NumericInput;
+use crate::noise::Lift;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AnimationMessage {
+    Animate(bool),
+    Loop(bool),
+    Frames(i32),
+    Fps(i32),
+    Speed(f32),
+    Time(f32),
+    Format(AnimationFormat),
+    Width(i32),
+    Quality(i32),
+    Null,
+}
+
+/// Animation moves the noise of every stage through time. The preview shows a
+/// single moment and the export renders every frame of the loop.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AnimationControls {
+    pub animate: bool,
+    /// Make the last frame lead back into the first.
+    pub looping: bool,
+    pub frames: i32,
+    pub fps: i32,
+    /// How far the noise travels in one second.
+    pub speed: f32,
+    /// The moment shown in the preview, in seconds.
+    pub time: f32,
+    pub format: Option<AnimationFormat>,
+    /// Width in pixels of animated files, the height follows the source.
+    pub width: i32,
+    /// From 1 to 100, what it trades off depends on the format.
+    pub quality: i32,
+    #[serde(skip)]
+    pub dirty: bool,
+}
+
+impl Default for AnimationControls {
+    fn default() -> Self {
+        Self {
+            animate: false,
+            looping: false,
+            frames: 96,
+            fps: 24,
+            speed: 0.25,
+            time: 0.0,
+            format: Some(AnimationFormat::Frames),
+            width: 600,
+            quality: 80,
+            dirty: false,
+        }
+    }
+}
+
+impl<'a> AnimationControls {
+    pub fn update(&mut self, message: AnimationMessage) {
+        use AnimationMessage::*;
+        self.dirty = true;
+        match message {
+            Animate(b) => self.animate = b,
+            Loop(b) => self.looping = b,
+            Frames(n) => {
+                self.frames = n;
+                self.clamp_time();
+            }
+            Fps(n) => {
+                self.fps = n;
+                self.clamp_time();
+            }
+            Speed(s) => self.speed = s,
+            Time(t) => self.time = t,
+            Format(f) => {
+                self.format = Some(f);
+                self.dirty = false;
+            }
+            Width(w) => {
+                self.width = w;
+                self.dirty = false;
+            }
+            Quality(q) => {
+                self.quality = q;
+                self.dirty = false;
+            }
+            Null => self.dirty = false,
+        }
+    }
+
+    /// Bring the settings of a loaded preset within the ranges of the inputs,
+    /// a preset can hold anything.
+    pub fn clamp_ranges(&mut self) {
+        self.frames = self.frames.clamp(2, 1000);
+        self.fps = self.fps.clamp(1, 60);
+        self.width = self.width.clamp(64, 4000);
+        self.quality = self.quality.clamp(1, 100);
+        self.time = self.time.max(0.0);
+        self.clamp_time();
+        self.dirty = false;
+    }
+
+    /// Keep the preview within the loop when it gets shorter.
+    fn clamp_time(&mut self) {
+        let time = self.time.min(self.duration());
+        self.dirty = time != self.time;
+        self.time = time;
+    }
+
+    /// Length of the loop in seconds.
+    pub fn duration(&self) -> f32 {
+        self.frames as f32 / self.fps as f32
+    }
+
+    /// The time at which `frame` is shown.
+    pub fn frame_time(&self, frame: i32) -> f32 {
+        frame as f32 / self.fps as f32
+    }
+
+    /// Where the noise is sampled at the preview time. A loop goes once
+    /// around a circle as long as the distance travelled in a straight line.
+    pub fn lift(&self) -> Lift {
+        if !self.animate {
+            Lift::Plane
+        } else if self.looping {
+            Lift::Loop {
+                angle: TAU * self.time as f64 / self.duration() as f64,
+                radius: self.speed as f64 * self.duration() as f64 / TAU,
+            }
+        } else {
+            Lift::Time((self.time * self.speed) as f64)
+        }
+    }
+
+    pub fn view(&self) -> Element<'a, AnimationMessage> {
+        use AnimationMessage::*;
+        let mut col = Column::new().push(Rule::horizontal(5)).push(Container::new(
+            toggler("Animate".to_owned(), self.animate, Animate).text_size(15),
+        ));
+        if self.animate {
+            col = col
+                .push(
+                    text(
+                        "Animated noise is sampled in 3D, or 4D when looping, so the first \
+                         frame is not the still image.",
+                    )
+                    .size(15),
+                )
+                .push(Container::new(
+                    toggler("Loop".to_owned(), self.looping, Loop).text_size(15),
+                ))
+                .push(NumericInput::new(
+                    "Frames".to_string(),
+                    self.frames,
+                    2..=1000,
+                    1,
+                    0,
+                    Frames,
+                ))
+                .push(NumericInput::new(
+                    "FPS".to_string(),
+                    self.fps,
+                    1..=60,
+                    1,
+                    0,
+                    Fps,
+                ))
+                .push(text(format!("Duration {:.2} s", self.duration())).size(15))
+                .push(NumericInput::new(
+                    "Speed".to_string(),
+                    self.speed,
+                    0.01..=5.0,
+                    0.01,
+                    2,
+                    Speed,
+                ))
+                .push(NumericInput::new(
+                    "Time".to_string(),
+                    self.time,
+                    0.0..=self.duration(),
+                    1.0 / self.fps as f32,
+                    2,
+                    Time,
+                ))
+                .push(LPickList::new(
+                    "Animation Format".to_string(),
+                    vec![
+                        AnimationFormat::Frames,
+                        AnimationFormat::Gif,
+                        AnimationFormat::Apng,
+                        AnimationFormat::WebP,
+                    ],
+                    self.format,
+                    |x| x.map_or(Null, Format),
+                ));
+            if self.format != Some(AnimationFormat::Frames) {
+                col = col
+                    .push(NumericInput::new(
+                        "Width".to_string(),
+                        self.width,
+                        64..=4000,
+                        1,
+                        0,
+                        Width,
+                    ))
+                    .push(NumericInput::new(
+                        "Quality".to_string(),
+                        self.quality,
+                        1..=100,
+                        1,
+                        0,
+                        Quality,
+                    ));
+            }
+        }
+        col.spacing(7).into()
+    }
+}
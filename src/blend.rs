@@ -0,0 +1,205 @@
+use iced::widget::{Column, Rule};
+use iced::Element;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::gui::lpicklist::LPickList;
+use crate::gui::numeric_input::NumericInput;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum BlendMessage {
+    Mode(BlendMode),
+    Opacity(f32),
+    Null,
+}
+
+/// How the color of the warped image combines with the source beneath it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum BlendMode {
+    Normal,
+    Multiply,
+    Screen,
+    Overlay,
+    Difference,
+}
+
+impl std::fmt::Display for BlendMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                BlendMode::Normal => "Normal",
+                BlendMode::Multiply => "Multiply",
+                BlendMode::Screen => "Screen",
+                BlendMode::Overlay => "Overlay",
+                BlendMode::Difference => "Difference",
+            }
+        )
+    }
+}
+
+impl BlendMode {
+    /// Blend a channel of the warped image `a` with the same channel of the
+    /// source `b`.
+    fn blend(&self, a: f32, b: f32) -> f32 {
+        match self {
+            BlendMode::Normal => a,
+            BlendMode::Multiply => a * b,
+            BlendMode::Screen => 1.0 - (1.0 - a) * (1.0 - b),
+            BlendMode::Overlay => {
+                if b < 0.5 {
+                    2.0 * a * b
+                } else {
+                    1.0 - 2.0 * (1.0 - a) * (1.0 - b)
+                }
+            }
+            BlendMode::Difference => (a - b).abs(),
+        }
+    }
+}
+
+/// Composites the warped image over the unwarped source. Like the color
+/// adjustments it runs on the cached result of a render.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BlendControls {
+    pub mode: Option<BlendMode>,
+    pub opacity: f32,
+    #[serde(skip)]
+    pub dirty: bool,
+}
+
+impl Default for BlendControls {
+    fn default() -> Self {
+        Self {
+            mode: Some(BlendMode::Normal),
+            opacity: 1.0,
+            dirty: false,
+        }
+    }
+}
+
+impl<'a> BlendControls {
+    pub fn update(&mut self, message: BlendMessage) {
+        use BlendMessage::*;
+        self.dirty = true;
+        match message {
+            Mode(m) => self.mode = Some(m),
+            Opacity(o) => self.opacity = o,
+            Null => self.dirty = false,
+        }
+    }
+
+    /// True when the warped image covers the source completely.
+    pub fn is_identity(&self) -> bool {
+        self.mode.unwrap_or(BlendMode::Normal) == BlendMode::Normal && self.opacity == 1.0
+    }
+
+    /// Blend a buffer of RGBA pixels in place with the `source` pixels under
+    /// them.
+    pub fn apply(&self, pixels: &mut [u8], source: &[u8]) {
+        if self.is_identity() {
+            return;
+        }
+        let mode = self.mode.unwrap_or(BlendMode::Normal);
+        pixels
+            .par_chunks_mut(4)
+            .zip(source.par_chunks(4))
+            .for_each(|(p, s)| {
+                let top = p[3] as f32 / 255.0 * self.opacity;
+                let bottom = s[3] as f32 / 255.0;
+                let alpha = top + bottom * (1.0 - top);
+                if alpha == 0.0 {
+                    p.copy_from_slice(&[0; 4]);
+                    return;
+                }
+                for i in 0..3 {
+                    let (a, b) = (p[i] as f32 / 255.0, s[i] as f32 / 255.0);
+                    let c = (mode.blend(a, b) * top + b * bottom * (1.0 - top)) / alpha;
+                    p[i] = (c.clamp(0.0, 1.0) * 255.0).round() as u8;
+                }
+                p[3] = (alpha * 255.0).round() as u8;
+            });
+    }
+
+    pub fn view(&self) -> Element<'a, BlendMessage> {
+        use BlendMessage::*;
+        Column::new()
+            .push(Rule::horizontal(5))
+            .push(LPickList::new(
+                "Blend".to_string(),
+                vec![
+                    BlendMode::Normal,
+                    BlendMode::Multiply,
+                    BlendMode::Screen,
+                    BlendMode::Overlay,
+                    BlendMode::Difference,
+                ],
+                self.mode,
+                |x| x.map_or(Null, Mode),
+            ))
+            .push(NumericInput::new(
+                "Opacity".to_string(),
+                self.opacity,
+                0.0..=1.0,
+                0.01,
+                2,
+                Opacity,
+            ))
+            .spacing(7)
+            .into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn modes_blend_channels() {
+        let (a, b) = (0.25, 0.5);
+        let cases = [
+            (BlendMode::Normal, 0.25),
+            (BlendMode::Multiply, 0.125),
+            (BlendMode::Screen, 0.625),
+            (BlendMode::Overlay, 0.5),
+            (BlendMode::Difference, 0.25),
+        ];
+        for (mode, expected) in cases {
+            assert!((mode.blend(a, b) - expected).abs() < 1e-6, "{mode}");
+        }
+        assert!((BlendMode::Overlay.blend(0.25, 0.25) - 0.125).abs() < 1e-6);
+    }
+
+    #[test]
+    fn opacity_mixes_with_the_source() {
+        let source = [200, 100, 0, 255];
+        let blend = |mode: BlendMode, opacity: f32| {
+            let mut pixels = [50, 150, 250, 255];
+            BlendControls {
+                mode: Some(mode),
+                opacity,
+                dirty: false,
+            }
+            .apply(&mut pixels, &source);
+            pixels
+        };
+        assert_eq!(blend(BlendMode::Normal, 1.0), [50, 150, 250, 255]);
+        assert_eq!(blend(BlendMode::Multiply, 0.0), source);
+        assert_eq!(blend(BlendMode::Normal, 0.5), [125, 125, 125, 255]);
+        assert_eq!(blend(BlendMode::Difference, 1.0), [150, 50, 250, 255]);
+    }
+
+    #[test]
+    fn transparent_pixels_stay_transparent() {
+        let mut pixels = [10, 20, 30, 0];
+        BlendControls {
+            mode: Some(BlendMode::Screen),
+            opacity: 1.0,
+            dirty: false,
+        }
+        .apply(&mut pixels, &[40, 50, 60, 0]);
+        assert_eq!(pixels, [0; 4]);
+    }
+}
@@ -0,0 +1,255 @@
+use iced::widget::{button, text, text_input, toggler, Column, Container, Row, Rule};
+use iced::Element;
+use palette::{FromColor, Okhsl, Srgb};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use wassily::prelude::{
+    paint_shader, pt, Canvas, Color, GradientStop, LinearGradient, Shape, SpreadMode, Transform,
+};
+
+use crate::gui::numeric_input::NumericInput;
+use crate::sample::parse_hex;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ColorMessage {
+    HueRotation(f32),
+    Saturation(f32),
+    Lightness(f32),
+    Contrast(f32),
+    Gamma(f32),
+    GradientMap(bool),
+    StopPosition(usize, f32),
+    StopColorSet(usize, String),
+    StopColor,
+    AddStop,
+    RemoveStop(usize),
+}
+
+/// A color of the gradient map and the lightness it is placed at, the color is
+/// kept as the hex string being edited.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Stop {
+    pub position: f32,
+    pub color: String,
+}
+
+impl Stop {
+    fn new(position: f32, color: &str) -> Self {
+        Self {
+            position,
+            color: color.to_string(),
+        }
+    }
+}
+
+/// Adjustments applied to the warped image, all of them work in Okhsl space.
+/// They don't change the warp, so they run on the cached result of a render.
+/// With the gradient map on, the lightness of each pixel first picks its color
+/// from the gradient and the adjustments apply to that.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ColorControls {
+    pub hue_rotation: f32,
+    pub saturation: f32,
+    pub lightness: f32,
+    pub contrast: f32,
+    pub gamma: f32,
+    pub gradient_map: bool,
+    pub stops: Vec<Stop>,
+    #[serde(skip)]
+    pub dirty: bool,
+}
+
+impl Default for ColorControls {
+    fn default() -> Self {
+        Self {
+            hue_rotation: 0.0,
+            saturation: 1.0,
+            lightness: 0.0,
+            contrast: 1.0,
+            gamma: 1.0,
+            gradient_map: false,
+            stops: vec![Stop::new(0.0, "#000000"), Stop::new(1.0, "#ffffff")],
+            dirty: false,
+        }
+    }
+}
+
+impl<'a> ColorControls {
+    pub fn update(&mut self, message: ColorMessage) {
+        use ColorMessage::*;
+        self.dirty = true;
+        match message {
+            HueRotation(r) => self.hue_rotation = r,
+            Saturation(s) => self.saturation = s,
+            Lightness(l) => self.lightness = l,
+            Contrast(c) => self.contrast = c,
+            Gamma(g) => self.gamma = g,
+            GradientMap(b) => self.gradient_map = b,
+            StopPosition(i, p) => self.stops[i].position = p,
+            StopColorSet(i, c) => {
+                self.stops[i].color = c;
+                self.dirty = false;
+            }
+            StopColor => {}
+            AddStop => self.stops.push(Stop::new(0.5, "#808080")),
+            RemoveStop(i) => {
+                self.stops.remove(i);
+            }
+        }
+    }
+
+    fn is_identity(&self) -> bool {
+        !self.gradient_map
+            && self.hue_rotation == 0.0
+            && self.saturation == 1.0
+            && self.lightness == 0.0
+            && self.contrast == 1.0
+            && self.gamma == 1.0
+    }
+
+    /// The gradient map as a table of 256 colors indexed by lightness. The
+    /// gradient is drawn the same way as the dominos, on a 256 x 1 canvas.
+    fn gradient(&self) -> Vec<[u8; 3]> {
+        let mut stops: Vec<(f32, Color)> = self
+            .stops
+            .iter()
+            .map(|s| (s.position, parse_hex(&s.color).unwrap_or(Color::BLACK)))
+            .collect();
+        stops.sort_by(|a, b| a.0.total_cmp(&b.0));
+        let stops = stops
+            .into_iter()
+            .map(|(position, color)| GradientStop::new(position, color))
+            .collect();
+        let mut canvas = Canvas::new(256, 1);
+        if let Some(grad) = LinearGradient::new(
+            pt(0.0, 0.0),
+            pt(256.0, 0.0),
+            stops,
+            SpreadMode::Pad,
+            Transform::identity(),
+        ) {
+            let shader = paint_shader(grad);
+            Shape::new()
+                .rect_xywh(pt(0.0, 0.0), pt(256.0, 1.0))
+                .fill_paint(&shader)
+                .no_stroke()
+                .draw(&mut canvas);
+        }
+        canvas
+            .pixmap
+            .pixels()
+            .iter()
+            .map(|c| {
+                let c = c.demultiply();
+                [c.red(), c.green(), c.blue()]
+            })
+            .collect()
+    }
+
+    /// Adjust a buffer of RGBA pixels in place.
+    pub fn apply(&self, pixels: &mut [u8]) {
+        if self.is_identity() {
+            return;
+        }
+        let gradient = if self.gradient_map {
+            Some(self.gradient())
+        } else {
+            None
+        };
+        pixels.par_chunks_mut(4).for_each(|p| {
+            let mut rgb = Srgb::new(p[0], p[1], p[2]).into_format::<f32>();
+            if let Some(gradient) = &gradient {
+                let hsl: Okhsl = Okhsl::from_color(rgb);
+                let [r, g, b] = gradient[(hsl.lightness.clamp(0.0, 1.0) * 255.0).round() as usize];
+                rgb = Srgb::new(r, g, b).into_format::<f32>();
+            }
+            let mut hsl: Okhsl = Okhsl::from_color(rgb);
+            let mut l = hsl.lightness.powf(1.0 / self.gamma);
+            l = (l - 0.5) * self.contrast + 0.5 + self.lightness;
+            hsl.lightness = l.clamp(0.0, 1.0);
+            hsl.saturation = (hsl.saturation * self.saturation).clamp(0.0, 1.0);
+            hsl.hue += self.hue_rotation;
+            let rgb: Srgb = Srgb::from_color(hsl);
+            let rgb = rgb.into_format::<u8>();
+            p[0] = rgb.red;
+            p[1] = rgb.green;
+            p[2] = rgb.blue;
+        });
+    }
+
+    pub fn view(&self) -> Element<'a, ColorMessage> {
+        use ColorMessage::*;
+        let mut col = Column::new().push(Rule::horizontal(5)).push(Container::new(
+            toggler("Gradient Map".to_owned(), self.gradient_map, GradientMap).text_size(15),
+        ));
+        if self.gradient_map {
+            let count = self.stops.len();
+            for (i, stop) in self.stops.iter().enumerate() {
+                let mut stop_row = Row::new().spacing(10).push(
+                    text_input("#rrggbb", &stop.color)
+                        .on_input(move |c| StopColorSet(i, c))
+                        .size(15)
+                        .width(90)
+                        .on_submit(StopColor),
+                );
+                // A gradient needs at least two stops.
+                if count > 2 {
+                    stop_row =
+                        stop_row.push(button(text("Remove").size(15)).on_press(RemoveStop(i)));
+                }
+                col = col.push(stop_row).push(NumericInput::new(
+                    "Position".to_string(),
+                    stop.position,
+                    0.0..=1.0,
+                    0.01,
+                    2,
+                    move |p| StopPosition(i, p),
+                ));
+            }
+            col = col.push(button(text("Add Stop").size(15)).on_press(AddStop));
+        }
+        col.push(NumericInput::new(
+            "Hue Rotation".to_string(),
+            self.hue_rotation,
+            0.0..=360.0,
+            1.0,
+            0,
+            HueRotation,
+        ))
+        .push(NumericInput::new(
+            "Saturation".to_string(),
+            self.saturation,
+            0.0..=2.0,
+            0.01,
+            2,
+            Saturation,
+        ))
+        .push(NumericInput::new(
+            "Lightness".to_string(),
+            self.lightness,
+            -0.5..=0.5,
+            0.01,
+            2,
+            Lightness,
+        ))
+        .push(NumericInput::new(
+            "Contrast".to_string(),
+            self.contrast,
+            0.0..=2.0,
+            0.01,
+            2,
+            Contrast,
+        ))
+        .push(NumericInput::new(
+            "Gamma".to_string(),
+            self.gamma,
+            0.2..=5.0,
+            0.01,
+            2,
+            Gamma,
+        ))
+        .spacing(7)
+        .into()
+    }
+}
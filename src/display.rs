@@ -0,0 +1,133 @@
+use palette::{FromColor, Okhsl, Srgb};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use wassily::prelude::{pt, DynamicImage, Point};
+
+use crate::render::Render;
+use crate::Controls;
+
+/// Distance in pixels between the arrows drawn over the displacement field.
+const ARROW_SPACING: u32 = 40;
+
+/// What the preview shows, the warped image, the image repeated 3 x 3 to check
+/// that it tiles, or a picture of the displacement field that produces it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ViewMode {
+    Image,
+    Tiled,
+    Magnitude,
+    Direction,
+}
+
+impl ViewMode {
+    /// True for the views of the displacement field.
+    pub fn is_field(&self) -> bool {
+        matches!(self, ViewMode::Magnitude | ViewMode::Direction)
+    }
+}
+
+impl std::fmt::Display for ViewMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                ViewMode::Image => "Image",
+                ViewMode::Tiled => "Tiled 3 x 3",
+                ViewMode::Magnitude => "Magnitude",
+                ViewMode::Direction => "Direction",
+            }
+        )
+    }
+}
+
+/// Render the displacement field of the warp of `img` as a `width` x `height`
+/// picture. The magnitude is shown as a heatmap relative to the largest
+/// displacement and the direction as a hue.
+pub fn visualize(
+    controls: &Controls,
+    img: &DynamicImage,
+    scale: f32,
+    width: u32,
+    height: u32,
+    keep_going: impl Fn() -> bool + Sync,
+) -> Option<Vec<u8>> {
+    let render = Render::new(controls, img, scale, width, height);
+    let field = render.displacements(0..height, keep_going)?;
+    let max = field
+        .iter()
+        .map(|d| d.x.hypot(d.y))
+        .fold(0.0, f32::max)
+        .max(f32::EPSILON);
+    let direction = controls.view == Some(ViewMode::Direction);
+    let mut pixels: Vec<u8> = field
+        .par_iter()
+        .flat_map_iter(|d| {
+            let hsl: Okhsl = if direction {
+                Okhsl::new(d.y.atan2(d.x).to_degrees(), 0.9, 0.65)
+            } else {
+                let t = d.x.hypot(d.y) / max;
+                Okhsl::new(260.0 - 230.0 * t, 0.9, 0.15 + 0.7 * t)
+            };
+            let rgb: Srgb = Srgb::from_color(hsl);
+            let rgb = rgb.into_format::<u8>();
+            [rgb.red, rgb.green, rgb.blue, 255]
+        })
+        .collect();
+    if controls.arrows {
+        arrows(&mut pixels, &field, width, height, max);
+    }
+    Some(pixels)
+}
+
+/// Repeat a `width` x `height` image `n` times in both directions.
+pub fn tile(pixels: &[u8], width: u32, height: u32, n: u32) -> Vec<u8> {
+    let row = 4 * width as usize;
+    let mut tiled = Vec::with_capacity(pixels.len() * (n * n) as usize);
+    for _ in 0..n {
+        for line in pixels.chunks(row).take(height as usize) {
+            for _ in 0..n {
+                tiled.extend_from_slice(line);
+            }
+        }
+    }
+    tiled
+}
+
+/// Draw an arrow along the displacement every `ARROW_SPACING` pixels, the
+/// largest displacement gets an arrow almost as long as the spacing.
+fn arrows(pixels: &mut [u8], field: &[Point], width: u32, height: u32, max: f32) {
+    let s = 0.9 * ARROW_SPACING as f32 / max;
+    for y in (ARROW_SPACING / 2..height).step_by(ARROW_SPACING as usize) {
+        for x in (ARROW_SPACING / 2..width).step_by(ARROW_SPACING as usize) {
+            let d = field[(y * width + x) as usize];
+            let a = pt(x as f32, y as f32);
+            let b = pt(a.x + s * d.x, a.y + s * d.y);
+            line(pixels, width, height, a, b);
+            // The head is two short strokes swept back from the tip.
+            let theta = d.y.atan2(d.x);
+            let len = 0.3 * s * d.x.hypot(d.y);
+            for turn in [-2.6f32, 2.6] {
+                let c = pt(
+                    b.x + len * (theta + turn).cos(),
+                    b.y + len * (theta + turn).sin(),
+                );
+                line(pixels, width, height, b, c);
+            }
+        }
+    }
+}
+
+/// Draw a white line from `a` to `b`, one pixel wide.
+fn line(pixels: &mut [u8], width: u32, height: u32, a: Point, b: Point) {
+    let n = (b.x - a.x).abs().max((b.y - a.y).abs()).ceil() as u32;
+    for k in 0..=n {
+        let t = if n == 0 { 0.0 } else { k as f32 / n as f32 };
+        let x = (a.x + t * (b.x - a.x)).round();
+        let y = (a.y + t * (b.y - a.y)).round();
+        if x >= 0.0 && y >= 0.0 && (x as u32) < width && (y as u32) < height {
+            let i = 4 * (y as u32 * width + x as u32) as usize;
+            pixels[i..i + 4].copy_from_slice(&[255; 4]);
+        }
+    }
+}
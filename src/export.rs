@@ -0,0 +1,449 @@
+use std::ffi::CStr;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::mem::MaybeUninit;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::{ptr, slice};
+
+use directories::UserDirs;
+use image::codecs::gif::{GifEncoder, Repeat};
+use image::{Delay, Frame, ImageResult, RgbaImage};
+use libwebp_sys::{
+    WebPAnimEncoder, WebPAnimEncoderAdd, WebPAnimEncoderAssemble, WebPAnimEncoderDelete,
+    WebPAnimEncoderGetError, WebPAnimEncoderNewInternal, WebPAnimEncoderOptions,
+    WebPAnimEncoderOptionsInitInternal, WebPConfig, WebPData, WebPDataClear, WebPGetMuxABIVersion,
+    WebPPicture, WebPPictureFree, WebPPictureImportRGBA,
+};
+use serde::{Deserialize, Serialize};
+use tiff::encoder::{colortype, TiffEncoder, TiffKind};
+use tiff::TiffResult;
+use wassily::prelude::DynamicImage;
+
+use crate::animation::AnimationControls;
+use crate::render::Render;
+use crate::Controls;
+
+/// Number of rows rendered and handed to the encoder at a time. Only one band
+/// of the output is ever held in memory, no matter how large the export is.
+const TILE_ROWS: u32 = 256;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ExportFormat {
+    Png,
+    Tiff,
+}
+
+impl ExportFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Png => "png",
+            ExportFormat::Tiff => "tiff",
+        }
+    }
+}
+
+impl std::fmt::Display for ExportFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                ExportFormat::Png => "PNG",
+                ExportFormat::Tiff => "TIFF",
+            }
+        )
+    }
+}
+
+/// How the frames of an animation are exported.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum AnimationFormat {
+    Frames,
+    Gif,
+    Apng,
+    WebP,
+}
+
+impl AnimationFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            AnimationFormat::Frames => "",
+            AnimationFormat::Gif => "gif",
+            AnimationFormat::Apng => "png",
+            AnimationFormat::WebP => "webp",
+        }
+    }
+}
+
+impl std::fmt::Display for AnimationFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                AnimationFormat::Frames => "PNG Frames",
+                AnimationFormat::Gif => "GIF",
+                AnimationFormat::Apng => "APNG",
+                AnimationFormat::WebP => "WebP",
+            }
+        )
+    }
+}
+
+/// The size of the exported image. Values under 256 are taken to be inches at
+/// 300 DPI, anything else is a size in pixels. If only the width is given the
+/// height follows from the aspect ratio of the source.
+pub fn export_size(controls: &Controls, w: u32, h: u32) -> (u32, u32) {
+    let aspect_ratio = w as f32 / h as f32;
+    let (mut width, mut height) = (w, h);
+    if let Ok(w) = controls.export_width.parse::<f32>() {
+        if w < 256.0 {
+            width = (300.0 * w).round() as u32;
+        } else {
+            width = w as u32;
+        }
+        if let Ok(h) = controls.export_height.parse::<f32>() {
+            if h < 256.0 {
+                height = (300.0 * h).round() as u32;
+            } else {
+                height = h as u32;
+            }
+        } else {
+            height = (width as f32 / aspect_ratio) as u32;
+        }
+    };
+    (width, height)
+}
+
+/// The first `warp_<n>.<extension>` in the downloads directory that is free.
+pub fn export_path(extension: &str) -> Result<PathBuf, String> {
+    let dirs = UserDirs::new().ok_or("there is no home directory")?;
+    let dir = dirs
+        .download_dir()
+        .ok_or("there is no downloads directory")?;
+    let path = format!(r"{}/{}", dir.to_string_lossy(), "warp");
+    let mut num = 0;
+    let mut sketch = PathBuf::from(format!(r"{path}_{num}"));
+    sketch.set_extension(extension);
+    while sketch.exists() {
+        num += 1;
+        sketch = PathBuf::from(format!(r"{path}_{num}"));
+        sketch.set_extension(extension);
+    }
+    Ok(sketch)
+}
+
+/// Render the warp of `img` at the export size and write it to the downloads
+/// directory. The image is rendered in bands of `TILE_ROWS` rows which are
+/// streamed straight into the encoder. Returns where the image was saved.
+pub fn export(controls: &Controls, img: &DynamicImage) -> Result<PathBuf, String> {
+    let (width, height) = export_size(controls, img.width(), img.height());
+    let render = Render::new(controls, img, 1.0, width, height);
+    let format = controls.export_format.unwrap_or(ExportFormat::Png);
+    let path = export_path(format.extension())?;
+    match format {
+        ExportFormat::Png => {
+            write_png(&path, &render, controls, width, height).map_err(|e| e.to_string())?
+        }
+        ExportFormat::Tiff => {
+            let file = BufWriter::new(File::create(&path).map_err(|e| e.to_string())?);
+            // Classic TIFF uses 32 bit offsets, larger images need BigTIFF.
+            let result = if 4 * width as u64 * height as u64 > u32::MAX as u64 {
+                TiffEncoder::new_big(file)
+                    .and_then(|tiff| write_tiff(tiff, &render, controls, width, height))
+            } else {
+                TiffEncoder::new(file)
+                    .and_then(|tiff| write_tiff(tiff, &render, controls, width, height))
+            };
+            result.map_err(|e| e.to_string())?
+        }
+    }
+    Ok(path)
+}
+
+/// Render every frame of the animation at the export size and write them as a
+/// numbered sequence of PNGs into a new folder in the downloads directory.
+fn export_frames(controls: &Controls, img: &DynamicImage) -> Result<PathBuf, String> {
+    let (width, height) = export_size(controls, img.width(), img.height());
+    let dir = export_path("")?;
+    fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+    for frame in 0..controls.animation.frames {
+        let controls = controls.at(controls.animation.frame_time(frame));
+        let render = Render::new(&controls, img, 1.0, width, height);
+        let path = dir.join(format!("frame_{frame:04}.png"));
+        write_png(&path, &render, &controls, width, height).map_err(|e| e.to_string())?;
+    }
+    Ok(dir)
+}
+
+/// Export the animation in the chosen format. A sequence of frames is
+/// rendered at the export size, animated files at the width set for them.
+/// Returns a note on what was saved and whether it loops seamlessly.
+pub fn export_animation(controls: &Controls, img: &DynamicImage) -> Result<String, String> {
+    let animation = &controls.animation;
+    let format = animation.format.unwrap_or(AnimationFormat::Frames);
+    let path = if format == AnimationFormat::Frames {
+        export_frames(controls, img)?
+    } else {
+        write_animation(controls, img, format)?
+    };
+    let seam = if seamless(controls) {
+        "a seamless loop"
+    } else {
+        "not a seamless loop"
+    };
+    Ok(format!(
+        "Saved {} frames to {}, {seam}",
+        animation.frames,
+        path.display()
+    ))
+}
+
+/// An animation loops seamlessly when the frame after the last would be the
+/// same as the first. That takes the loop mode and keyframes that end the way
+/// they start.
+fn seamless(controls: &Controls) -> bool {
+    let start = controls.at(0.0);
+    let end = controls.at(controls.animation.duration());
+    controls.animation.looping && start.stages == end.stages && start.color == end.color
+}
+
+/// Write the frames of the animation to a single animated file.
+fn write_animation(
+    controls: &Controls,
+    img: &DynamicImage,
+    format: AnimationFormat,
+) -> Result<PathBuf, String> {
+    let animation = &controls.animation;
+    let width = animation.width as u32;
+    let height = (width as f32 * img.height() as f32 / img.width() as f32)
+        .round()
+        .max(1.0) as u32;
+    let path = export_path(format.extension())?;
+    let frames =
+        (0..animation.frames).map(|frame| render_frame(controls, img, frame, width, height));
+    match format {
+        AnimationFormat::Gif => {
+            write_gif(&path, frames, animation, width, height).map_err(|e| e.to_string())?
+        }
+        AnimationFormat::Apng => {
+            write_apng(&path, frames, animation, width, height).map_err(|e| e.to_string())?
+        }
+        AnimationFormat::WebP => {
+            write_webp(&path, frames, animation, width, height).map_err(|e| e.to_string())?
+        }
+        AnimationFormat::Frames => {}
+    }
+    Ok(path)
+}
+
+/// The finished pixels of one frame of the animation.
+fn render_frame(
+    controls: &Controls,
+    img: &DynamicImage,
+    frame: i32,
+    width: u32,
+    height: u32,
+) -> Vec<u8> {
+    let controls = controls.at(controls.animation.frame_time(frame));
+    let render = Render::new(&controls, img, 1.0, width, height);
+    bands(height)
+        .flat_map(|rows| band(&render, &controls, rows))
+        .collect()
+}
+
+fn bands(height: u32) -> impl Iterator<Item = Range<u32>> {
+    (0..height)
+        .step_by(TILE_ROWS as usize)
+        .map(move |i| i..(i + TILE_ROWS).min(height))
+}
+
+/// The finished pixels of a band of rows.
+fn band(render: &Render, controls: &Controls, rows: Range<u32>) -> Vec<u8> {
+    let mut pixels = render.rows(rows.clone(), || true).unwrap();
+    controls.color.apply(&mut pixels);
+    if !controls.blend.is_identity() {
+        controls.blend.apply(&mut pixels, &render.source(rows));
+    }
+    pixels
+}
+
+fn write_png(
+    path: &Path,
+    render: &Render,
+    controls: &Controls,
+    width: u32,
+    height: u32,
+) -> Result<(), png::EncodingError> {
+    let file = BufWriter::new(File::create(path)?);
+    let mut encoder = png::Encoder::new(file, width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut stream = encoder.write_header()?.into_stream_writer()?;
+    for rows in bands(height) {
+        stream.write_all(&band(render, controls, rows))?;
+    }
+    stream.finish()
+}
+
+fn write_tiff<K: TiffKind>(
+    mut tiff: TiffEncoder<BufWriter<File>, K>,
+    render: &Render,
+    controls: &Controls,
+    width: u32,
+    height: u32,
+) -> TiffResult<()> {
+    let mut image = tiff.new_image::<colortype::RGBA8>(width, height)?;
+    image.rows_per_strip(TILE_ROWS)?;
+    for rows in bands(height) {
+        image.write_strip(&band(render, controls, rows))?;
+    }
+    image.finish()
+}
+
+/// GIF has a palette of 256 colors per frame. Quality sets how many of the
+/// pixels are sampled to pick the palette, at 100 every pixel is.
+fn write_gif(
+    path: &Path,
+    frames: impl Iterator<Item = Vec<u8>>,
+    animation: &AnimationControls,
+    width: u32,
+    height: u32,
+) -> ImageResult<()> {
+    let speed = 30 - (animation.quality - 1) * 29 / 99;
+    let mut encoder = GifEncoder::new_with_speed(BufWriter::new(File::create(path)?), speed);
+    encoder.set_repeat(Repeat::Infinite)?;
+    let delay = Delay::from_numer_denom_ms(1000, animation.fps as u32);
+    for pixels in frames {
+        let buffer = RgbaImage::from_raw(width, height, pixels).unwrap();
+        encoder.encode_frame(Frame::from_parts(buffer, 0, 0, delay))?;
+    }
+    Ok(())
+}
+
+/// APNG is lossless, quality trades the speed of compression for size.
+fn write_apng(
+    path: &Path,
+    frames: impl Iterator<Item = Vec<u8>>,
+    animation: &AnimationControls,
+    width: u32,
+    height: u32,
+) -> Result<(), png::EncodingError> {
+    let file = BufWriter::new(File::create(path)?);
+    let mut encoder = png::Encoder::new(file, width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.set_compression(match animation.quality {
+        ..=33 => png::Compression::Fast,
+        34..=66 => png::Compression::Default,
+        _ => png::Compression::Best,
+    });
+    encoder.set_animated(animation.frames as u32, 0)?;
+    encoder.set_frame_delay(1, animation.fps as u16)?;
+    let mut writer = encoder.write_header()?;
+    for pixels in frames {
+        writer.write_image_data(&pixels)?;
+    }
+    writer.finish()
+}
+
+/// Lossy WebP, quality is the quality of the encoder. Each frame is
+/// compressed as soon as it is rendered, only the compressed frames are kept
+/// until the file is assembled.
+fn write_webp(
+    path: &Path,
+    frames: impl Iterator<Item = Vec<u8>>,
+    animation: &AnimationControls,
+    width: u32,
+    height: u32,
+) -> io::Result<()> {
+    let mut config = WebPConfig::new().unwrap();
+    config.lossless = 0;
+    config.quality = animation.quality as f32;
+    let mut encoder = WebPAnimation::new(width, height)?;
+    for (i, pixels) in frames.enumerate() {
+        let timestamp = 1000 * i as i32 / animation.fps;
+        encoder.add(&pixels, width, height, timestamp, &config)?;
+    }
+    fs::write(
+        path,
+        encoder.finish(1000 * animation.frames / animation.fps)?,
+    )
+}
+
+/// A libwebp animation encoder, it loops forever.
+struct WebPAnimation(*mut WebPAnimEncoder);
+
+impl WebPAnimation {
+    fn new(width: u32, height: u32) -> io::Result<Self> {
+        let encoder = unsafe {
+            let mut options = MaybeUninit::<WebPAnimEncoderOptions>::uninit();
+            WebPAnimEncoderOptionsInitInternal(options.as_mut_ptr(), WebPGetMuxABIVersion());
+            WebPAnimEncoderNewInternal(
+                width as i32,
+                height as i32,
+                options.as_ptr(),
+                WebPGetMuxABIVersion(),
+            )
+        };
+        if encoder.is_null() {
+            return Err(io::Error::other("Could not create the WebP encoder"));
+        }
+        Ok(Self(encoder))
+    }
+
+    /// Compress the RGBA `pixels` of the frame shown from `timestamp`
+    /// milliseconds on. The pixels are not needed once this returns.
+    fn add(
+        &mut self,
+        pixels: &[u8],
+        width: u32,
+        height: u32,
+        timestamp: i32,
+        config: &WebPConfig,
+    ) -> io::Result<()> {
+        let mut picture =
+            WebPPicture::new().map_err(|_| io::Error::other("Could not create a WebP picture"))?;
+        picture.use_argb = 1;
+        picture.width = width as i32;
+        picture.height = height as i32;
+        let added = unsafe {
+            let added = WebPPictureImportRGBA(&mut picture, pixels.as_ptr(), width as i32 * 4) != 0
+                && WebPAnimEncoderAdd(self.0, &mut picture, timestamp, config) != 0;
+            WebPPictureFree(&mut picture);
+            added
+        };
+        if added {
+            Ok(())
+        } else {
+            Err(self.error())
+        }
+    }
+
+    /// The bytes of the file, the last frame is shown until `end`.
+    fn finish(self, end: i32) -> io::Result<Vec<u8>> {
+        let mut data = WebPData::default();
+        unsafe {
+            if WebPAnimEncoderAdd(self.0, ptr::null_mut(), end, ptr::null()) == 0
+                || WebPAnimEncoderAssemble(self.0, &mut data) == 0
+            {
+                return Err(self.error());
+            }
+            let bytes = slice::from_raw_parts(data.bytes, data.size).to_vec();
+            WebPDataClear(&mut data);
+            Ok(bytes)
+        }
+    }
+
+    fn error(&self) -> io::Error {
+        let message = unsafe { CStr::from_ptr(WebPAnimEncoderGetError(self.0)) };
+        io::Error::other(message.to_string_lossy())
+    }
+}
+
+impl Drop for WebPAnimation {
+    fn drop(&mut self) {
+        unsafe { WebPAnimEncoderDelete(self.0) }
+    }
+}
@@ -1,2 +1,3 @@
+pub mod image_picker;
 pub mod lpicklist;
 pub mod numeric_input;
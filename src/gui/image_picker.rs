@@ -0,0 +1,120 @@
+use iced_native::event::{self, Event};
+use iced_native::image::{self, Handle};
+use iced_native::layout::{self, Layout};
+use iced_native::renderer;
+use iced_native::widget::Tree;
+use iced_native::{mouse, Clipboard, Element, Length, Point, Rectangle, Shell, Size, Widget};
+
+/// An image that reports where it is clicked, as a fraction of its width and
+/// height. The image is scaled to fit its bounds and centered.
+pub struct ImagePicker<Message> {
+    handle: Handle,
+    on_pick: Box<dyn Fn(f32, f32) -> Message>,
+}
+
+impl<Message> ImagePicker<Message> {
+    pub fn new(handle: Handle, on_pick: impl Fn(f32, f32) -> Message + 'static) -> Self {
+        Self {
+            handle,
+            on_pick: Box::new(on_pick),
+        }
+    }
+
+    /// Where the image is drawn within `bounds`.
+    fn image_bounds<Renderer>(&self, renderer: &Renderer, bounds: Rectangle) -> Rectangle
+    where
+        Renderer: image::Renderer<Handle = Handle>,
+    {
+        let Size { width, height } = renderer.dimensions(&self.handle);
+        let scale = (bounds.width / width as f32).min(bounds.height / height as f32);
+        let size = Size::new(width as f32 * scale, height as f32 * scale);
+        Rectangle {
+            x: bounds.x + (bounds.width - size.width) / 2.0,
+            y: bounds.y + (bounds.height - size.height) / 2.0,
+            width: size.width,
+            height: size.height,
+        }
+    }
+}
+
+impl<Message, Renderer> Widget<Message, Renderer> for ImagePicker<Message>
+where
+    Renderer: image::Renderer<Handle = Handle>,
+{
+    fn width(&self) -> Length {
+        Length::Fill
+    }
+
+    fn height(&self) -> Length {
+        Length::Fill
+    }
+
+    fn layout(&self, _renderer: &Renderer, limits: &layout::Limits) -> layout::Node {
+        layout::Node::new(limits.width(Length::Fill).height(Length::Fill).max())
+    }
+
+    fn draw(
+        &self,
+        _state: &Tree,
+        renderer: &mut Renderer,
+        _theme: &Renderer::Theme,
+        _style: &renderer::Style,
+        layout: Layout<'_>,
+        _cursor_position: Point,
+        _viewport: &Rectangle,
+    ) {
+        let bounds = self.image_bounds(renderer, layout.bounds());
+        renderer.draw(self.handle.clone(), bounds);
+    }
+
+    fn on_event(
+        &mut self,
+        _state: &mut Tree,
+        event: Event,
+        layout: Layout<'_>,
+        cursor_position: Point,
+        renderer: &Renderer,
+        _clipboard: &mut dyn Clipboard,
+        shell: &mut Shell<'_, Message>,
+    ) -> event::Status {
+        if let Event::Mouse(mouse::Event::ButtonPressed(mouse::Button::Left)) = event {
+            let bounds = self.image_bounds(renderer, layout.bounds());
+            if bounds.contains(cursor_position) {
+                shell.publish((self.on_pick)(
+                    (cursor_position.x - bounds.x) / bounds.width,
+                    (cursor_position.y - bounds.y) / bounds.height,
+                ));
+                return event::Status::Captured;
+            }
+        }
+        event::Status::Ignored
+    }
+
+    fn mouse_interaction(
+        &self,
+        _state: &Tree,
+        layout: Layout<'_>,
+        cursor_position: Point,
+        _viewport: &Rectangle,
+        renderer: &Renderer,
+    ) -> mouse::Interaction {
+        if self
+            .image_bounds(renderer, layout.bounds())
+            .contains(cursor_position)
+        {
+            mouse::Interaction::Crosshair
+        } else {
+            mouse::Interaction::default()
+        }
+    }
+}
+
+impl<'a, Message, Renderer> From<ImagePicker<Message>> for Element<'a, Message, Renderer>
+where
+    Message: 'a,
+    Renderer: image::Renderer<Handle = Handle> + 'a,
+{
+    fn from(picker: ImagePicker<Message>) -> Self {
+        Element::new(picker)
+    }
+}
@@ -0,0 +1,518 @@
+use iced::widget::{button, text, toggler, Column, Container, Row, Rule};
+use iced::Element;
+use serde::{Deserialize, Serialize};
+
+use crate::gui::lpicklist::LPickList;
+use crate::gui::numeric_input::NumericInput;
+use crate::noise::{BaseNoise, NoiseControls, NoiseFunctionName, WorleyDistance, WorleyReturn};
+use crate::stage::WarpStage;
+use crate::Coordinates;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum KeyframeMessage {
+    Enable(bool),
+    EasingSet(Easing),
+    Add,
+    Set(usize),
+    Remove(usize),
+    Frame(usize, i32),
+    Switch(usize, i32),
+    Null,
+}
+
+/// How the numeric fields move from one keyframe to the next.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Easing {
+    Linear,
+    EaseIn,
+    EaseOut,
+    EaseInOut,
+}
+
+impl std::fmt::Display for Easing {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Easing::Linear => "Linear",
+                Easing::EaseIn => "Ease In",
+                Easing::EaseOut => "Ease Out",
+                Easing::EaseInOut => "Ease In Out",
+            }
+        )
+    }
+}
+
+impl Easing {
+    /// Map the progress `t` between two keyframes, both in `0..=1`.
+    fn ease(&self, t: f32) -> f32 {
+        match self {
+            Easing::Linear => t,
+            Easing::EaseIn => t * t * t,
+            Easing::EaseOut => 1.0 - (1.0 - t).powi(3),
+            Easing::EaseInOut => {
+                if t < 0.5 {
+                    4.0 * t * t * t
+                } else {
+                    1.0 - (2.0 - 2.0 * t).powi(3) / 2.0
+                }
+            }
+        }
+    }
+}
+
+/// A snapshot of the warp chain and the hue, reached at `frame`. Fields that
+/// can't be interpolated, like the noise function or the coordinates, take
+/// over from the keyframe before at the `switch` frame.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Keyframe {
+    pub frame: i32,
+    pub switch: i32,
+    pub stages: Vec<StagePose>,
+    pub hue_rotation: f32,
+}
+
+/// What a keyframe keeps of a stage. The images of the image noise are not
+/// part of it, they always come from the stage itself.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StagePose {
+    pub theta_noise: NoisePose,
+    pub radius_noise: NoisePose,
+    pub coordinates: Option<Coordinates>,
+    pub sync: bool,
+    pub muted: bool,
+}
+
+impl Default for StagePose {
+    fn default() -> Self {
+        Self::from(&WarpStage::default())
+    }
+}
+
+impl From<&WarpStage> for StagePose {
+    fn from(stage: &WarpStage) -> Self {
+        Self {
+            theta_noise: NoisePose::from(&stage.theta_noise),
+            radius_noise: NoisePose::from(&stage.radius_noise),
+            coordinates: stage.coordinates,
+            sync: stage.sync,
+            muted: stage.muted,
+        }
+    }
+}
+
+impl StagePose {
+    fn apply(&self, stage: &mut WarpStage) {
+        self.theta_noise.apply(&mut stage.theta_noise);
+        self.radius_noise.apply(&mut stage.radius_noise);
+        stage.coordinates = self.coordinates;
+        stage.sync = self.sync;
+        stage.muted = self.muted;
+    }
+}
+
+/// What a keyframe keeps of a noise, the numbers that are interpolated and
+/// the choices that switch.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NoisePose {
+    pub function: Option<NoiseFunctionName>,
+    pub factor: f32,
+    pub scale_x: f32,
+    pub scale_y: f32,
+    pub octaves: i32,
+    pub frequency: f32,
+    pub lacunarity: f32,
+    pub persistence: f32,
+    pub attenuation: f32,
+    pub sin_x_freq: f32,
+    pub sin_y_freq: f32,
+    pub offset_x: f32,
+    pub offset_y: f32,
+    pub rotation: f32,
+    pub shear_x: f32,
+    pub shear_y: f32,
+    pub worley_distance: Option<WorleyDistance>,
+    pub worley_return: Option<WorleyReturn>,
+    pub checker_size: i32,
+    pub base: Option<BaseNoise>,
+    pub seed: u32,
+}
+
+impl Default for NoisePose {
+    fn default() -> Self {
+        Self::from(&NoiseControls::default())
+    }
+}
+
+impl From<&NoiseControls> for NoisePose {
+    fn from(n: &NoiseControls) -> Self {
+        Self {
+            function: n.function,
+            factor: n.factor,
+            scale_x: n.scale_x,
+            scale_y: n.scale_y,
+            octaves: n.octaves,
+            frequency: n.frequency,
+            lacunarity: n.lacunarity,
+            persistence: n.persistence,
+            attenuation: n.attenuation,
+            sin_x_freq: n.sin_x_freq,
+            sin_y_freq: n.sin_y_freq,
+            offset_x: n.offset_x,
+            offset_y: n.offset_y,
+            rotation: n.rotation,
+            shear_x: n.shear_x,
+            shear_y: n.shear_y,
+            worley_distance: n.worley_distance,
+            worley_return: n.worley_return,
+            checker_size: n.checker_size,
+            base: n.base,
+            seed: n.seed,
+        }
+    }
+}
+
+impl NoisePose {
+    fn apply(&self, n: &mut NoiseControls) {
+        n.function = self.function;
+        n.factor = self.factor;
+        n.scale_x = self.scale_x;
+        n.scale_y = self.scale_y;
+        n.octaves = self.octaves;
+        n.frequency = self.frequency;
+        n.lacunarity = self.lacunarity;
+        n.persistence = self.persistence;
+        n.attenuation = self.attenuation;
+        n.sin_x_freq = self.sin_x_freq;
+        n.sin_y_freq = self.sin_y_freq;
+        n.offset_x = self.offset_x;
+        n.offset_y = self.offset_y;
+        n.rotation = self.rotation;
+        n.shear_x = self.shear_x;
+        n.shear_y = self.shear_y;
+        n.worley_distance = self.worley_distance;
+        n.worley_return = self.worley_return;
+        n.checker_size = self.checker_size;
+        n.base = self.base;
+        n.seed = self.seed;
+    }
+}
+
+/// Keyframes override the warp of each frame of an animation with a blend of
+/// the two keyframes around it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct KeyframeControls {
+    pub enabled: bool,
+    pub easing: Option<Easing>,
+    pub keys: Vec<Keyframe>,
+    #[serde(skip)]
+    pub dirty: bool,
+}
+
+impl Default for KeyframeControls {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            easing: Some(Easing::Linear),
+            keys: vec![],
+            dirty: false,
+        }
+    }
+}
+
+impl<'a> KeyframeControls {
+    /// Capturing a keyframe needs the current warp, `Add` and `Set` are
+    /// handled by the owner of the controls.
+    pub fn update(&mut self, message: KeyframeMessage) {
+        use KeyframeMessage::*;
+        self.dirty = true;
+        match message {
+            Enable(b) => self.enabled = b,
+            EasingSet(e) => self.easing = Some(e),
+            Remove(i) => {
+                self.keys.remove(i);
+            }
+            Frame(i, f) => self.keys[i].frame = f,
+            Switch(i, f) => self.keys[i].switch = f,
+            Add | Set(_) | Null => self.dirty = false,
+        }
+    }
+
+    /// Capture a new keyframe at `frame`, switching halfway from the one
+    /// before it.
+    pub fn add(&mut self, stages: &[WarpStage], hue_rotation: f32, frame: i32) {
+        let before = self.keys.iter().map(|k| k.frame).max().unwrap_or(0);
+        self.keys.push(Keyframe {
+            frame,
+            switch: (before + frame) / 2,
+            stages: stages.iter().map(StagePose::from).collect(),
+            hue_rotation,
+        });
+        self.dirty = true;
+    }
+
+    /// Replace the warp of keyframe `i` with the current one.
+    pub fn set(&mut self, i: usize, stages: &[WarpStage], hue_rotation: f32) {
+        self.keys[i].stages = stages.iter().map(StagePose::from).collect();
+        self.keys[i].hue_rotation = hue_rotation;
+        self.dirty = true;
+    }
+
+    /// Drop stage `i` from every keyframe, it was removed from the chain.
+    pub fn remove_stage(&mut self, i: usize) {
+        for key in self.keys.iter_mut().filter(|k| i < k.stages.len()) {
+            key.stages.remove(i);
+        }
+    }
+
+    /// Swap stages `i` and `j` in every keyframe, as they were in the chain.
+    pub fn swap_stages(&mut self, i: usize, j: usize) {
+        for key in self.keys.iter_mut().filter(|k| i.max(j) < k.stages.len()) {
+            key.stages.swap(i, j);
+        }
+    }
+
+    /// Pose the warp chain and hue for `frame`, which can fall between
+    /// frames. Before the first keyframe and after the last they hold still.
+    /// A field that is the same in every keyframe is not animated, it keeps
+    /// the value it has in `stages` or `hue_rotation`, as does a stage that
+    /// no keyframe has.
+    pub fn pose(&self, frame: f32, stages: &mut [WarpStage], hue_rotation: &mut f32) {
+        if !self.enabled || self.keys.is_empty() {
+            return;
+        }
+        let mut keys: Vec<&Keyframe> = self.keys.iter().collect();
+        keys.sort_by_key(|k| k.frame);
+        let (first, last) = (keys[0], keys[keys.len() - 1]);
+        let (a, b, t) = if frame <= first.frame as f32 {
+            (first, first, 0.0)
+        } else if let Some(w) = keys.windows(2).find(|w| frame < w[1].frame as f32) {
+            let t = (frame - w[0].frame as f32) / (w[1].frame - w[0].frame).max(1) as f32;
+            (w[0], w[1], self.easing.unwrap_or(Easing::Linear).ease(t))
+        } else {
+            (last, last, 0.0)
+        };
+        let switched = frame >= b.switch as f32;
+        for (i, stage) in stages.iter_mut().enumerate() {
+            let (x, y) = match (a.stages.get(i), b.stages.get(i)) {
+                (Some(x), Some(y)) => (x, y),
+                (Some(x), None) | (None, Some(x)) => (x, x),
+                (None, None) => continue,
+            };
+            let poses: Vec<&StagePose> = keys.iter().filter_map(|k| k.stages.get(i)).collect();
+            let mut pose = StagePose::from(&*stage);
+            mix_stage(&mut pose, &poses, x, y, t, switched);
+            pose.apply(stage);
+        }
+        if keys.iter().any(|k| k.hue_rotation != a.hue_rotation) {
+            *hue_rotation = lerp_hue(a.hue_rotation, b.hue_rotation, t);
+        }
+    }
+
+    pub fn view(&self) -> Element<'a, KeyframeMessage> {
+        use KeyframeMessage::*;
+        let mut col = Column::new().push(Rule::horizontal(5)).push(Container::new(
+            toggler("Keyframes".to_owned(), self.enabled, Enable).text_size(15),
+        ));
+        if self.enabled {
+            col = col.push(LPickList::new(
+                "Easing".to_string(),
+                vec![
+                    Easing::Linear,
+                    Easing::EaseIn,
+                    Easing::EaseOut,
+                    Easing::EaseInOut,
+                ],
+                self.easing,
+                |x| x.map_or(Null, EasingSet),
+            ));
+            for (i, key) in self.keys.iter().enumerate() {
+                col = col
+                    .push(text(format!("Key {}", i + 1)))
+                    .push(
+                        Row::new()
+                            .spacing(5)
+                            .push(button(text("Set").size(15)).on_press(Set(i)))
+                            .push(button(text("Remove").size(15)).on_press(Remove(i))),
+                    )
+                    .push(NumericInput::new(
+                        "Frame".to_string(),
+                        key.frame,
+                        0..=1000,
+                        1,
+                        0,
+                        move |f| Frame(i, f),
+                    ))
+                    .push(NumericInput::new(
+                        "Switch At".to_string(),
+                        key.switch,
+                        0..=1000,
+                        1,
+                        0,
+                        move |f| Switch(i, f),
+                    ));
+            }
+            col = col.push(button(text("Add Keyframe").size(15)).on_press(Add));
+        }
+        col.spacing(7).into()
+    }
+}
+
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + t * (b - a)
+}
+
+/// Turn the hue from `a` to `b` degrees the short way around the circle.
+fn lerp_hue(a: f32, b: f32, t: f32) -> f32 {
+    let d = (b - a + 180.0).rem_euclid(360.0) - 180.0;
+    (a + t * d).rem_euclid(360.0)
+}
+
+/// Interpolate a number of the pose between the keyframes `a` and `b`, unless
+/// it is the same in all of the `keys`.
+fn tween<P>(live: &mut f32, keys: &[&P], a: &P, b: &P, t: f32, field: impl Fn(&P) -> f32) {
+    if keys.iter().any(|k| field(k) != field(a)) {
+        *live = lerp(field(a), field(b), t);
+    }
+}
+
+/// Take a choice of the pose from the keyframe in effect, unless it is the
+/// same in all of the `keys`.
+fn switch<P, T: PartialEq + Clone>(live: &mut T, keys: &[&P], base: &P, field: impl Fn(&P) -> &T) {
+    if keys.iter().any(|k| field(k) != field(base)) {
+        *live = field(base).clone();
+    }
+}
+
+fn mix_stage(
+    pose: &mut StagePose,
+    keys: &[&StagePose],
+    a: &StagePose,
+    b: &StagePose,
+    t: f32,
+    switched: bool,
+) {
+    let base = if switched { b } else { a };
+    let theta: Vec<&NoisePose> = keys.iter().map(|k| &k.theta_noise).collect();
+    mix_noise(
+        &mut pose.theta_noise,
+        &theta,
+        &a.theta_noise,
+        &b.theta_noise,
+        t,
+        switched,
+    );
+    let radius: Vec<&NoisePose> = keys.iter().map(|k| &k.radius_noise).collect();
+    mix_noise(
+        &mut pose.radius_noise,
+        &radius,
+        &a.radius_noise,
+        &b.radius_noise,
+        t,
+        switched,
+    );
+    switch(&mut pose.coordinates, keys, base, |s| &s.coordinates);
+    switch(&mut pose.sync, keys, base, |s| &s.sync);
+    switch(&mut pose.muted, keys, base, |s| &s.muted);
+}
+
+fn mix_noise(
+    pose: &mut NoisePose,
+    keys: &[&NoisePose],
+    a: &NoisePose,
+    b: &NoisePose,
+    t: f32,
+    switched: bool,
+) {
+    tween(&mut pose.factor, keys, a, b, t, |n| n.factor);
+    tween(&mut pose.scale_x, keys, a, b, t, |n| n.scale_x);
+    tween(&mut pose.scale_y, keys, a, b, t, |n| n.scale_y);
+    tween(&mut pose.frequency, keys, a, b, t, |n| n.frequency);
+    tween(&mut pose.lacunarity, keys, a, b, t, |n| n.lacunarity);
+    tween(&mut pose.persistence, keys, a, b, t, |n| n.persistence);
+    tween(&mut pose.attenuation, keys, a, b, t, |n| n.attenuation);
+    tween(&mut pose.sin_x_freq, keys, a, b, t, |n| n.sin_x_freq);
+    tween(&mut pose.sin_y_freq, keys, a, b, t, |n| n.sin_y_freq);
+    tween(&mut pose.offset_x, keys, a, b, t, |n| n.offset_x);
+    tween(&mut pose.offset_y, keys, a, b, t, |n| n.offset_y);
+    tween(&mut pose.rotation, keys, a, b, t, |n| n.rotation);
+    tween(&mut pose.shear_x, keys, a, b, t, |n| n.shear_x);
+    tween(&mut pose.shear_y, keys, a, b, t, |n| n.shear_y);
+    let base = if switched { b } else { a };
+    switch(&mut pose.function, keys, base, |n| &n.function);
+    switch(&mut pose.octaves, keys, base, |n| &n.octaves);
+    switch(&mut pose.worley_distance, keys, base, |n| {
+        &n.worley_distance
+    });
+    switch(&mut pose.worley_return, keys, base, |n| &n.worley_return);
+    switch(&mut pose.checker_size, keys, base, |n| &n.checker_size);
+    switch(&mut pose.base, keys, base, |n| &n.base);
+    switch(&mut pose.seed, keys, base, |n| &n.seed);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn easings_start_and_end_on_the_keyframes() {
+        for easing in [
+            Easing::Linear,
+            Easing::EaseIn,
+            Easing::EaseOut,
+            Easing::EaseInOut,
+        ] {
+            assert_eq!(easing.ease(0.0), 0.0, "{easing}");
+            assert_eq!(easing.ease(1.0), 1.0, "{easing}");
+        }
+        assert_eq!(Easing::EaseInOut.ease(0.5), 0.5);
+    }
+
+    #[test]
+    fn hue_turns_the_short_way() {
+        assert_eq!(lerp_hue(350.0, 10.0, 0.5), 0.0);
+        assert_eq!(lerp_hue(10.0, 350.0, 0.25), 5.0);
+        assert_eq!(lerp_hue(90.0, 180.0, 0.5), 135.0);
+    }
+
+    #[test]
+    fn fields_left_alone_by_the_keyframes_stay_live() {
+        let mut controls = KeyframeControls {
+            enabled: true,
+            ..Default::default()
+        };
+        let mut stages = vec![WarpStage::default()];
+        controls.add(&stages, 0.0, 0);
+        stages[0].theta_noise.factor = 100.0;
+        controls.add(&stages, 0.0, 10);
+        stages[0].theta_noise.scale_x = 3.0;
+        let mut hue_rotation = 45.0;
+        controls.pose(5.0, &mut stages, &mut hue_rotation);
+        assert_eq!(stages[0].theta_noise.factor, 300.0);
+        assert_eq!(stages[0].theta_noise.scale_x, 3.0);
+        assert_eq!(hue_rotation, 45.0);
+    }
+
+    #[test]
+    fn removed_stages_stay_removed() {
+        let mut controls = KeyframeControls {
+            enabled: true,
+            ..Default::default()
+        };
+        let mut stages = vec![WarpStage::default(), WarpStage::default()];
+        stages[1].theta_noise.factor = 20.0;
+        controls.add(&stages, 0.0, 0);
+        stages[1].theta_noise.factor = 40.0;
+        controls.add(&stages, 0.0, 10);
+        stages.remove(0);
+        controls.remove_stage(0);
+        let mut hue_rotation = 0.0;
+        controls.pose(5.0, &mut stages, &mut hue_rotation);
+        assert_eq!(stages.len(), 1);
+        assert_eq!(stages[0].theta_noise.factor, 30.0);
+    }
+}
@@ -1,31 +1,47 @@
-use directories::UserDirs;
 use iced::{
-    widget::{
-        button, column, image, radio, row, scrollable, text, text_input, toggler, Column,
-        Container, Rule,
-    },
+    widget::{button, image, row, scrollable, text, text_input, toggler, Column, Container, Rule},
     Application, Command, Element, Settings, Theme,
 };
 use iced_native::widget::scrollable::Properties;
-use rayon::prelude::*;
-use std::path::{Path, PathBuf};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
-use wassily::prelude::{
-    imageops, img_noise, noise2d, noise2d_01, open, pt, Colorful, Coord, DynamicImage, ImageBuffer,
-    NoiseOpts, Rgba, Seedable, Warp, WarpNode,
-};
+use wassily::prelude::{imageops, open, DynamicImage, ImageBuffer, Rgba};
 
+mod animation;
+mod blend;
+mod color;
+mod display;
 mod dominos;
+mod export;
 mod gui;
+mod keyframe;
 mod noise;
+mod render;
+mod sample;
+mod stage;
 
+use crate::animation::{AnimationControls, AnimationMessage};
+use crate::blend::{BlendControls, BlendMessage};
+use crate::color::{ColorControls, ColorMessage};
+use crate::display::{tile, visualize, ViewMode};
+use crate::export::{export, export_animation, ExportFormat};
+use crate::gui::image_picker::ImagePicker;
+use crate::gui::lpicklist::LPickList;
 use crate::gui::numeric_input::NumericInput;
-use crate::noise::*;
+use crate::keyframe::{KeyframeControls, KeyframeMessage};
+use crate::noise::{BaseNoise, NoiseFunctionName};
+use crate::render::{draw, Quality};
+use crate::sample::{Edge, Interpolation};
+use crate::stage::{StageMessage, WarpStage};
 use dominos::draw_dominos;
 
 const WIDTH: u32 = 4800;
 const HEIGHT: u32 = 3600;
 const SIZE: u32 = 400;
+const PREVIEW_SIZE: u32 = 1200;
 
 pub fn main() -> iced::Result {
     env_logger::init();
@@ -34,7 +50,7 @@ pub fn main() -> iced::Result {
     Warper::run(settings)
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum Coordinates {
     Polar,
     Cartesian,
@@ -53,54 +69,186 @@ impl From<Coordinates> for String {
 
 #[derive(Debug, Clone)]
 pub enum Message {
-    Angle(NoiseMessage),
-    Radius(NoiseMessage),
-    HueRotation(f32),
+    Stage(usize, StageMessage),
+    AddStage,
+    Color(ColorMessage),
+    Blend(BlendMessage),
+    Animation(AnimationMessage),
+    Keyframe(KeyframeMessage),
     Export,
-    ExportComplete(()),
+    ExportComplete(String),
+    Rendered(u64, Option<Vec<u8>>),
     PathSet(String),
     ImgPath,
-    CoordinatesMessage(Coordinates),
+    MaskPathSet(String),
+    MaskPath,
+    PresetPathSet(String),
+    SavePreset,
+    LoadPreset,
     WidthSet(String),
     HeightSet(String),
-    Sync(bool),
-    WarpTwice(bool),
+    ExportFormatSet(ExportFormat),
+    ViewSet(ViewMode),
+    Arrows(bool),
+    QualitySet(Quality),
+    InterpolationSet(Interpolation),
+    EdgeSet(Edge),
+    Tileable(bool),
+    Centered(bool),
+    CenterX(f32),
+    CenterY(f32),
+    PickCenter(bool),
+    CenterPicked(f32, f32),
+    EdgeColorSet(String),
+    EdgeColor,
     Null,
 }
 
-#[derive(Debug, Clone)]
+/// Everything that describes a warp, saved to and loaded from presets as JSON.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 struct Controls {
     img_path: String,
-    theta_noise: NoiseControls,
-    radius_noise: NoiseControls,
-    hue_rotation: f32,
-    coordinates: Option<Coordinates>,
+    mask_path: String,
+    #[serde(skip)]
+    mask: Option<Arc<DynamicImage>>,
+    stages: Vec<WarpStage>,
+    color: ColorControls,
+    blend: BlendControls,
+    animation: AnimationControls,
+    keyframes: KeyframeControls,
+    #[serde(skip)]
+    preset_path: String,
+    #[serde(skip)]
+    status: String,
+    #[serde(skip)]
     exporting: bool,
+    #[serde(skip)]
+    rendering: bool,
     export_width: String,
     export_height: String,
-    sync: bool,
-    warp_twice: bool,
+    export_format: Option<ExportFormat>,
+    view: Option<ViewMode>,
+    arrows: bool,
+    quality: Option<Quality>,
+    interpolation: Option<Interpolation>,
+    edge: Option<Edge>,
+    edge_color: String,
+    tileable: bool,
+    /// Measure the angle of polar warps around a center, given as a fraction
+    /// of the width and height.
+    centered: bool,
+    center_x: f32,
+    center_y: f32,
+    #[serde(skip)]
+    picking_center: bool,
+}
+
+impl Controls {
+    /// The controls for the moment `time` seconds into the animation, with
+    /// the keyframes applied.
+    fn at(&self, time: f32) -> Controls {
+        let mut controls = self.clone();
+        controls.animation.time = time;
+        if self.animation.animate {
+            let frame = time * self.animation.fps as f32;
+            self.keyframes.pose(
+                frame,
+                &mut controls.stages,
+                &mut controls.color.hue_rotation,
+            );
+            controls.clamp_octaves();
+        }
+        controls
+    }
+
+    /// Keep the fractal noises within the octaves allowed at the quality,
+    /// whether they were set live, loaded from a preset or posed by a keyframe.
+    fn clamp_octaves(&mut self) {
+        let max_octaves = self.quality.unwrap_or(Quality::Single).max_octaves();
+        for stage in self.stages.iter_mut() {
+            for noise in [&mut stage.theta_noise, &mut stage.radius_noise] {
+                noise.octaves = noise.octaves.min(max_octaves);
+            }
+        }
+    }
+
+    /// Read the mask from `mask_path`, no mask is used when there is no image
+    /// at the path.
+    fn load_mask(&mut self) {
+        self.mask = open(Path::new(&self.mask_path)).ok().map(Arc::new);
+    }
+
+    /// What keeps the warp from repeating seamlessly when it is tileable, if
+    /// anything does.
+    fn untileable(&self) -> Option<&'static str> {
+        if self.mask.is_some() {
+            return Some("a mask");
+        }
+        if self.edge.unwrap_or(Edge::Wrap) != Edge::Wrap {
+            return Some("edges that don't wrap");
+        }
+        for stage in self.stages.iter().filter(|stage| !stage.muted) {
+            if stage.coordinates.unwrap_or(Coordinates::Cartesian) != Coordinates::Cartesian {
+                return Some("polar or absolute coordinates");
+            }
+            let noises = if stage.sync {
+                vec![&stage.theta_noise]
+            } else {
+                vec![&stage.theta_noise, &stage.radius_noise]
+            };
+            for noise in noises {
+                match noise.function {
+                    Some(NoiseFunctionName::Checkerboard)
+                    | Some(NoiseFunctionName::Cylinders)
+                    | Some(NoiseFunctionName::Image) => {
+                        return Some("checkerboard, cylinders or image noise")
+                    }
+                    Some(NoiseFunctionName::SuperSimplex) => return Some("SuperSimplex noise"),
+                    _ => {}
+                }
+                let function = noise.function.unwrap_or(NoiseFunctionName::Fbm);
+                if function.is_fractal() && noise.base == Some(BaseNoise::SuperSimplex) {
+                    return Some("SuperSimplex noise");
+                }
+                if noise.rotation != 0.0 || noise.shear_x != 0.0 || noise.shear_y != 0.0 {
+                    return Some("rotated or sheared noise");
+                }
+            }
+        }
+        None
+    }
 }
 
 impl Default for Controls {
     fn default() -> Self {
         Self {
             img_path: String::from(""),
-            theta_noise: NoiseControls {
-                factor: 500.0,
-                ..Default::default()
-            },
-            radius_noise: NoiseControls {
-                factor: 1000.0,
-                ..Default::default()
-            },
-            hue_rotation: 0.0,
-            coordinates: Some(Coordinates::Cartesian),
+            mask_path: String::from(""),
+            mask: None,
+            stages: vec![WarpStage::default()],
+            color: ColorControls::default(),
+            blend: BlendControls::default(),
+            animation: AnimationControls::default(),
+            keyframes: KeyframeControls::default(),
+            preset_path: String::from(""),
+            status: String::from(""),
             export_width: String::from("inches / pixels"),
             export_height: String::from("inches / pixels"),
+            export_format: Some(ExportFormat::Png),
             exporting: false,
-            sync: true,
-            warp_twice: false,
+            rendering: false,
+            view: Some(ViewMode::Image),
+            arrows: false,
+            quality: Some(Quality::Single),
+            interpolation: Some(Interpolation::Bilinear),
+            edge: Some(Edge::Wrap),
+            edge_color: String::from("#000000"),
+            tileable: false,
+            centered: false,
+            center_x: 0.5,
+            center_y: 0.5,
+            picking_center: false,
         }
     }
 }
@@ -108,8 +256,11 @@ impl Default for Controls {
 #[derive(Debug, Clone)]
 struct Warper {
     controls: Controls,
-    img: DynamicImage,
+    img: Arc<DynamicImage>,
+    proxy: Arc<DynamicImage>,
+    warped: ImageBuffer<Rgba<u8>, Vec<u8>>,
     image: image::Handle,
+    generation: Arc<AtomicU64>,
 }
 
 impl Warper {
@@ -117,63 +268,95 @@ impl Warper {
         let controls = Controls::default();
         let img_data = draw_dominos(WIDTH, HEIGHT, SIZE).pixmap.take();
         let img = DynamicImage::ImageRgba8(ImageBuffer::from_raw(WIDTH, HEIGHT, img_data).unwrap());
-        let art_data = draw(&controls, &img);
-        let image = image::Handle::from_pixels(img.width(), img.height(), art_data);
-        Self {
+        let proxy = proxy(&img);
+        let warped_data = draw(
+            &controls,
+            &proxy,
+            proxy_scale(&img, &proxy),
+            proxy.width(),
+            proxy.height(),
+            || true,
+        )
+        .unwrap();
+        let warped = ImageBuffer::from_raw(proxy.width(), proxy.height(), warped_data).unwrap();
+        let mut warper = Self {
             controls,
-            img,
-            image,
+            img: Arc::new(img),
+            proxy: Arc::new(proxy),
+            warped,
+            image: image::Handle::from_pixels(1, 1, vec![0; 4]),
+            generation: Arc::new(AtomicU64::new(0)),
+        };
+        warper.recolor();
+        warper
+    }
+
+    /// Apply the color adjustments and the blend with the source to the last
+    /// warp that was rendered, which is much faster than rendering it again.
+    pub fn recolor(&mut self) {
+        let (w, h) = self.warped.dimensions();
+        let mut img_data = self.warped.as_raw().clone();
+        let view = self.controls.view.unwrap_or(ViewMode::Image);
+        if view.is_field() {
+            self.image = image::Handle::from_pixels(w, h, img_data);
+            return;
+        }
+        let controls = self.controls.at(self.controls.animation.time);
+        controls.color.apply(&mut img_data);
+        if !self.controls.blend.is_identity() {
+            self.controls
+                .blend
+                .apply(&mut img_data, self.proxy.to_rgba8().as_raw());
         }
+        self.image = if view == ViewMode::Tiled {
+            image::Handle::from_pixels(3 * w, 3 * h, tile(&img_data, w, h, 3))
+        } else {
+            image::Handle::from_pixels(w, h, img_data)
+        };
     }
 
-    pub fn draw(&mut self) {
-        let img_data = draw(&self.controls, &self.img);
-        self.image = image::Handle::from_pixels(self.img.width(), self.img.height(), img_data);
+    /// Start rendering the preview in the background. Bumping the generation
+    /// cancels any render that is still running, the last one to be started
+    /// is the only one whose result is shown.
+    pub fn draw(&mut self) -> Command<Message> {
+        let current = self.generation.fetch_add(1, Ordering::Relaxed) + 1;
+        self.controls.rendering = true;
+        let controls = self.controls.at(self.controls.animation.time);
+        let img = self.img.clone();
+        let proxy = self.proxy.clone();
+        let generation = self.generation.clone();
+        Command::perform(
+            blocking(move || Warper::render(controls, img, proxy, generation, current)),
+            |(current, img_data)| Message::Rendered(current, img_data),
+        )
     }
 
-    async fn print(handle: image::Handle, controls: Controls) {
-        let (w, h, pixels) = match handle.data() {
-            iced_native::image::Data::Path(_) => unreachable!(),
-            iced_native::image::Data::Bytes(_) => unreachable!(),
-            iced_native::image::Data::Rgba {
-                width,
-                height,
-                pixels,
-            } => (width, height, pixels),
+    fn render(
+        controls: Controls,
+        img: Arc<DynamicImage>,
+        proxy: Arc<DynamicImage>,
+        generation: Arc<AtomicU64>,
+        current: u64,
+    ) -> (u64, Option<Vec<u8>>) {
+        let scale = proxy_scale(&img, &proxy);
+        let keep_going = || generation.load(Ordering::Relaxed) == current;
+        let (w, h) = (proxy.width(), proxy.height());
+        let img_data = if controls.view.unwrap_or(ViewMode::Image).is_field() {
+            visualize(&controls, &proxy, scale, w, h, keep_going)
+        } else {
+            draw(&controls, &proxy, scale, w, h, keep_going)
         };
-        let img_buf: ImageBuffer<Rgba<u8>, Vec<u8>> =
-            ImageBuffer::from_vec(*w, *h, pixels.to_vec()).unwrap();
-        let aspect_ratio = *w as f32 / *h as f32;
-        let (mut width, mut height) = (*w, *h);
-        if let Ok(w) = controls.export_width.parse::<f32>() {
-            if w < 256.0 {
-                width = (300.0 * w).round() as u32;
-            } else {
-                width = w as u32;
-            }
-            if let Ok(h) = controls.export_height.parse::<f32>() {
-                if h < 256.0 {
-                    height = (300.0 * h).round() as u32;
-                } else {
-                    height = h as u32;
-                }
-            } else {
-                height = (width as f32 / aspect_ratio) as u32;
-            }
+        (current, img_data)
+    }
+
+    /// Export the image or the animation, returns the status to show.
+    fn print(img: Arc<DynamicImage>, controls: Controls) -> String {
+        let saved = if controls.animation.animate {
+            export_animation(&controls, &img)
+        } else {
+            export(&controls, &img).map(|path| format!("Saved {}", path.display()))
         };
-        let img_buf = imageops::resize(&img_buf, width, height, imageops::FilterType::CatmullRom);
-        let dirs = UserDirs::new().unwrap();
-        let dir = dirs.download_dir().unwrap();
-        let path = format!(r"{}/{}", dir.to_string_lossy(), "warp");
-        let mut num = 0;
-        let mut sketch = PathBuf::from(format!(r"{path}_{num}"));
-        sketch.set_extension("png");
-        while sketch.exists() {
-            num += 1;
-            sketch = PathBuf::from(format!(r"{path}_{num}"));
-            sketch.set_extension("png");
-        }
-        img_buf.save(sketch).unwrap();
+        saved.unwrap_or_else(|e| format!("Could not export: {e}"))
     }
 }
 
@@ -194,36 +377,102 @@ impl Application for Warper {
     fn update(&mut self, message: Self::Message) -> Command<Self::Message> {
         use Message::*;
         match message {
-            Angle(a) => {
-                self.controls.theta_noise.update(a);
-                if self.controls.theta_noise.dirty {
-                    self.draw()
+            Stage(i, m) => match m {
+                StageMessage::MoveUp => {
+                    self.controls.stages.swap(i - 1, i);
+                    self.controls.keyframes.swap_stages(i - 1, i);
+                    return self.draw();
+                }
+                StageMessage::MoveDown => {
+                    self.controls.stages.swap(i, i + 1);
+                    self.controls.keyframes.swap_stages(i, i + 1);
+                    return self.draw();
+                }
+                StageMessage::Remove => {
+                    self.controls.stages.remove(i);
+                    self.controls.keyframes.remove_stage(i);
+                    return self.draw();
+                }
+                m => {
+                    let stage = &mut self.controls.stages[i];
+                    stage.update(m);
+                    if stage.dirty {
+                        return self.draw();
+                    }
+                }
+            },
+            AddStage => {
+                self.controls.stages.push(WarpStage::default());
+                return self.draw();
+            }
+            Color(c) => {
+                self.controls.color.update(c);
+                if self.controls.color.dirty {
+                    self.recolor();
                 }
             }
-            Radius(r) => {
-                self.controls.radius_noise.update(r);
-                if self.controls.radius_noise.dirty {
-                    self.draw()
+            Blend(b) => {
+                self.controls.blend.update(b);
+                if self.controls.blend.dirty {
+                    self.recolor();
                 }
             }
-            HueRotation(r) => {
-                self.controls.hue_rotation = r;
-                self.draw()
+            Animation(a) => {
+                self.controls.animation.update(a);
+                if self.controls.animation.dirty {
+                    return self.draw();
+                }
+            }
+            Keyframe(k) => {
+                let frame = (self.controls.animation.time * self.controls.animation.fps as f32)
+                    .round() as i32;
+                let hue_rotation = self.controls.color.hue_rotation;
+                match k {
+                    KeyframeMessage::Add => {
+                        self.controls
+                            .keyframes
+                            .add(&self.controls.stages, hue_rotation, frame)
+                    }
+                    KeyframeMessage::Set(i) => {
+                        self.controls
+                            .keyframes
+                            .set(i, &self.controls.stages, hue_rotation)
+                    }
+                    k => self.controls.keyframes.update(k),
+                }
+                if self.controls.keyframes.dirty {
+                    return self.draw();
+                }
             }
             Export => {
                 self.controls.exporting = true;
-                let handel = self.image.clone();
+                self.controls.status = String::from("Exporting…");
+                let img = self.img.clone();
+                let controls = self.controls.clone();
                 return Command::perform(
-                    Warper::print(handel, self.controls.clone()),
+                    blocking(move || Warper::print(img, controls)),
                     ExportComplete,
                 );
             }
-            ExportComplete(_) => self.controls.exporting = false,
+            ExportComplete(status) => {
+                self.controls.exporting = false;
+                self.controls.status = status;
+            }
+            Rendered(current, img_data) => {
+                if current == self.generation.load(Ordering::Relaxed) {
+                    if let Some(img_data) = img_data {
+                        let (w, h) = (self.proxy.width(), self.proxy.height());
+                        self.warped = ImageBuffer::from_raw(w, h, img_data).unwrap();
+                        self.recolor();
+                    }
+                    self.controls.rendering = false;
+                }
+            }
             PathSet(p) => {
                 self.controls.img_path = p;
             }
             ImgPath => {
-                self.img = match open(Path::new(&self.controls.img_path)) {
+                let img = match open(Path::new(&self.controls.img_path)) {
                     Ok(img) => img,
                     Err(_) => {
                         let img_data = draw_dominos(WIDTH, HEIGHT, SIZE).pixmap.take();
@@ -232,23 +481,106 @@ impl Application for Warper {
                         )
                     }
                 };
-                self.draw()
+                self.proxy = Arc::new(proxy(&img));
+                self.img = Arc::new(img);
+                return self.draw();
             }
-            CoordinatesMessage(c) => {
-                self.controls.coordinates = Some(c);
-                self.draw()
+            PresetPathSet(p) => self.controls.preset_path = p,
+            SavePreset => {
+                let json = serde_json::to_string_pretty(&self.controls).unwrap();
+                self.controls.status = match fs::write(&self.controls.preset_path, json) {
+                    Ok(()) => format!("Saved preset to {}", self.controls.preset_path),
+                    Err(e) => format!("Could not save preset: {e}"),
+                };
+            }
+            LoadPreset => {
+                let controls = fs::read_to_string(&self.controls.preset_path)
+                    .map_err(|e| e.to_string())
+                    .and_then(|json| {
+                        serde_json::from_str::<Controls>(&json).map_err(|e| e.to_string())
+                    });
+                match controls {
+                    Err(e) => self.controls.status = format!("Could not load preset: {e}"),
+                    Ok(controls) => {
+                        self.controls = Controls {
+                            preset_path: self.controls.preset_path.clone(),
+                            exporting: self.controls.exporting,
+                            ..controls
+                        };
+                        self.controls.load_mask();
+                        self.controls.animation.clamp_ranges();
+                        self.controls.clamp_octaves();
+                        for stage in self.controls.stages.iter_mut() {
+                            stage.theta_noise.load_img();
+                            stage.radius_noise.load_img();
+                        }
+                        self.controls.status = String::new();
+                        return self.update(ImgPath);
+                    }
+                }
+            }
+            MaskPathSet(p) => self.controls.mask_path = p,
+            MaskPath => {
+                self.controls.load_mask();
+                return self.draw();
             }
             WidthSet(w) => {
                 self.controls.export_width = w;
             }
             HeightSet(h) => self.controls.export_height = h,
-            Sync(b) => {
-                self.controls.sync = b;
-                self.draw()
+            ExportFormatSet(f) => self.controls.export_format = Some(f),
+            ViewSet(v) => {
+                self.controls.view = Some(v);
+                return self.draw();
+            }
+            Arrows(b) => {
+                self.controls.arrows = b;
+                return self.draw();
+            }
+            QualitySet(q) => {
+                self.controls.quality = Some(q);
+                self.controls.clamp_octaves();
+                return self.draw();
+            }
+            InterpolationSet(i) => {
+                self.controls.interpolation = Some(i);
+                return self.draw();
+            }
+            EdgeSet(e) => {
+                self.controls.edge = Some(e);
+                return self.draw();
             }
-            WarpTwice(b) => {
-                self.controls.warp_twice = b;
-                self.draw()
+            EdgeColorSet(c) => self.controls.edge_color = c,
+            EdgeColor => return self.draw(),
+            Tileable(b) => {
+                self.controls.tileable = b;
+                return self.draw();
+            }
+            Centered(b) => {
+                self.controls.centered = b;
+                self.controls.picking_center &= b;
+                return self.draw();
+            }
+            CenterX(x) => {
+                self.controls.center_x = x;
+                return self.draw();
+            }
+            CenterY(y) => {
+                self.controls.center_y = y;
+                return self.draw();
+            }
+            PickCenter(b) => self.controls.picking_center = b,
+            CenterPicked(x, y) => {
+                // Any of the tiles of the tiled view picks the same point.
+                let (x, y) = if self.controls.view == Some(ViewMode::Tiled) {
+                    ((3.0 * x).fract(), (3.0 * y).fract())
+                } else {
+                    (x, y)
+                };
+                self.controls.center_x = x;
+                self.controls.center_y = y;
+                self.controls.picking_center = false;
+                return self.draw();
             }
             Null => {}
         }
@@ -257,8 +589,12 @@ impl Application for Warper {
 
     fn view(&self) -> Element<'_, Self::Message, iced::Renderer<Self::Theme>> {
         use Message::*;
-        let img_view = image::viewer(self.image.clone()).min_scale(0.75);
-        let img_container = Container::new(img_view).padding(20);
+        let img_container = if self.controls.picking_center {
+            Container::new(ImagePicker::new(self.image.clone(), CenterPicked))
+        } else {
+            Container::new(image::viewer(self.image.clone()).min_scale(0.75))
+        }
+        .padding(20);
         let mut control_panel = Column::new()
             .push(text("Image Path").width(200))
             .spacing(15)
@@ -269,32 +605,97 @@ impl Application for Warper {
                     .width(200)
                     .on_submit(ImgPath),
             )
-            .push(Rule::horizontal(5))
+            .push(text("Mask Path").width(200))
+            .push(
+                text_input("", &self.controls.mask_path)
+                    .on_input(MaskPathSet)
+                    .size(15)
+                    .width(200)
+                    .on_submit(MaskPath),
+            )
+            .push(text("Preset Path").width(200))
+            .push(
+                text_input("", &self.controls.preset_path)
+                    .on_input(PresetPathSet)
+                    .size(15)
+                    .width(200),
+            )
             .push(
-                column(
-                    [
-                        Coordinates::Polar,
-                        Coordinates::Cartesian,
-                        Coordinates::Absolute,
-                    ]
-                    .iter()
-                    .cloned()
-                    .map(|d| {
-                        radio(d, d, self.controls.coordinates, CoordinatesMessage)
-                            .text_size(15)
-                            .size(15)
-                    })
-                    .map(Element::from)
-                    .collect(),
+                row!(
+                    button(text("Save").size(15)).on_press(SavePreset),
+                    button(text("Load").size(15)).on_press(LoadPreset)
                 )
                 .spacing(15),
             )
-            .push(Container::new(
-                toggler("Sync".to_owned(), self.controls.sync, Sync).text_size(15),
+            .push(Rule::horizontal(5))
+            .push(LPickList::new(
+                "View".to_string(),
+                vec![
+                    ViewMode::Image,
+                    ViewMode::Tiled,
+                    ViewMode::Magnitude,
+                    ViewMode::Direction,
+                ],
+                self.controls.view,
+                |x| x.map_or(Null, ViewSet),
+            ));
+        if self.controls.view.unwrap_or(ViewMode::Image).is_field() {
+            control_panel = control_panel.push(Container::new(
+                toggler("Arrows".to_owned(), self.controls.arrows, Arrows).text_size(15),
+            ));
+        }
+        control_panel = control_panel
+            .push(LPickList::new(
+                "Quality".to_string(),
+                vec![
+                    Quality::Single,
+                    Quality::Grid2,
+                    Quality::Grid4,
+                    Quality::Adaptive,
+                ],
+                self.controls.quality,
+                |x| x.map_or(Null, QualitySet),
             ))
-            .push(Container::new(
-                toggler("Warp Twide".to_owned(), self.controls.warp_twice, WarpTwice).text_size(15),
+            .push(LPickList::new(
+                "Interpolation".to_string(),
+                vec![
+                    Interpolation::Nearest,
+                    Interpolation::Bilinear,
+                    Interpolation::Bicubic,
+                    Interpolation::Lanczos,
+                ],
+                self.controls.interpolation,
+                |x| x.map_or(Null, InterpolationSet),
             ))
+            .push(LPickList::new(
+                "Edges".to_string(),
+                vec![
+                    Edge::Wrap,
+                    Edge::Clamp,
+                    Edge::Mirror,
+                    Edge::Transparent,
+                    Edge::Color,
+                ],
+                self.controls.edge,
+                |x| x.map_or(Null, EdgeSet),
+            ));
+        if self.controls.edge == Some(Edge::Color) {
+            control_panel = control_panel.push(
+                text_input("#rrggbb", &self.controls.edge_color)
+                    .on_input(EdgeColorSet)
+                    .size(15)
+                    .width(90)
+                    .on_submit(EdgeColor),
+            );
+        }
+        control_panel = control_panel.push(Container::new(
+            toggler("Tileable".to_owned(), self.controls.tileable, Tileable).text_size(15),
+        ));
+        if let (true, Some(reason)) = (self.controls.tileable, self.controls.untileable()) {
+            control_panel =
+                control_panel.push(text(format!("Does not tile with {reason}.")).size(15));
+        }
+        control_panel = control_panel
             .push(
                 row!(
                     text("Width").size(15).width(90),
@@ -314,70 +715,90 @@ impl Application for Warper {
                         .width(90)
                 )
                 .spacing(15),
+            )
+            .push(LPickList::new(
+                "Export Format".to_string(),
+                vec![ExportFormat::Png, ExportFormat::Tiff],
+                self.controls.export_format,
+                |x| x.map_or(Null, ExportFormatSet),
+            ));
+        if self
+            .controls
+            .stages
+            .iter()
+            .any(|stage| stage.coordinates == Some(Coordinates::Polar))
+        {
+            control_panel = control_panel.push(Container::new(
+                toggler("Polar Center".to_owned(), self.controls.centered, Centered).text_size(15),
+            ));
+        }
+        if self.controls.centered
+            && self
+                .controls
+                .stages
+                .iter()
+                .any(|stage| stage.coordinates == Some(Coordinates::Polar))
+        {
+            control_panel = control_panel
+                .push(NumericInput::new(
+                    "Polar Center X".to_string(),
+                    self.controls.center_x,
+                    0.0..=1.0,
+                    0.01,
+                    2,
+                    CenterX,
+                ))
+                .push(NumericInput::new(
+                    "Polar Center Y".to_string(),
+                    self.controls.center_y,
+                    0.0..=1.0,
+                    0.01,
+                    2,
+                    CenterY,
+                ))
+                .push(Container::new(
+                    toggler(
+                        "Click to Place".to_owned(),
+                        self.controls.picking_center,
+                        PickCenter,
+                    )
+                    .text_size(15),
+                ));
+        }
+        let count = self.controls.stages.len();
+        let max_octaves = self
+            .controls
+            .quality
+            .unwrap_or(Quality::Single)
+            .max_octaves();
+        for (i, stage) in self.controls.stages.iter().enumerate() {
+            control_panel = control_panel.push(
+                stage
+                    .view(i, count, max_octaves)
+                    .map(move |m| Message::Stage(i, m)),
             );
-        let angle = NoiseControls::new(
-            self.controls.theta_noise.function,
-            self.controls.theta_noise.factor,
-            self.controls.theta_noise.scale_x,
-            self.controls.theta_noise.scale_y,
-            self.controls.theta_noise.octaves,
-            self.controls.theta_noise.frequency,
-            self.controls.theta_noise.sin_x_freq,
-            self.controls.theta_noise.sin_y_freq,
-            self.controls.theta_noise.img_noise_path.clone(),
-            self.controls.theta_noise.img.clone(),
-            self.controls.theta_noise.img_color_map,
-            self.controls.theta_noise.dirty,
-        );
-        control_panel = control_panel
-            .push(if self.controls.coordinates == Some(Coordinates::Polar) {
-                text("Angle")
-            } else {
-                text("X Coordinate")
-            })
-            .push(angle.view().map(Message::Angle));
-
-        if !self.controls.sync {
-            let radius = NoiseControls::new(
-                self.controls.radius_noise.function,
-                self.controls.radius_noise.factor,
-                self.controls.radius_noise.scale_x,
-                self.controls.radius_noise.scale_y,
-                self.controls.radius_noise.octaves,
-                self.controls.radius_noise.frequency,
-                self.controls.radius_noise.sin_x_freq,
-                self.controls.radius_noise.sin_y_freq,
-                self.controls.radius_noise.img_noise_path.clone(),
-                self.controls.radius_noise.img.clone(),
-                self.controls.radius_noise.img_color_map,
-                self.controls.radius_noise.dirty,
-            );
+        }
+        control_panel = control_panel.push(button(text("Add Stage").size(15)).on_press(AddStage));
+        control_panel = control_panel.push(self.controls.color.view().map(Message::Color));
+        control_panel = control_panel.push(self.controls.blend.view().map(Message::Blend));
+        control_panel = control_panel.push(self.controls.animation.view().map(Message::Animation));
+        if self.controls.animation.animate {
             control_panel =
-                control_panel.push(if self.controls.coordinates == Some(Coordinates::Polar) {
-                    text("Radius")
-                } else {
-                    text("Y Coordinate")
-                });
-            control_panel = control_panel.push(radius.view().map(Message::Radius))
+                control_panel.push(self.controls.keyframes.view().map(Message::Keyframe));
+        }
+        if self.controls.rendering {
+            control_panel = control_panel.push(text("Rendering…").size(15));
         }
-        control_panel = control_panel.push(NumericInput::new(
-            "Hue Rotation".to_string(),
-            self.controls.hue_rotation,
-            0.0..=360.0,
-            1.0,
-            0,
-            HueRotation,
-        ));
         let export_button = if self.controls.exporting {
             button(text("Export").size(15))
         } else {
             button(text("Export").size(15)).on_press(Export)
         };
-        control_panel = control_panel
-            .push(export_button)
-            .spacing(10)
-            .padding(20)
-            .width(250);
+        control_panel = control_panel.push(export_button);
+        if !self.controls.status.is_empty() {
+            control_panel = control_panel.push(text(&self.controls.status).size(15));
+        }
+        control_panel = control_panel.spacing(10).padding(20).width(250);
         let scroll_panel = scrollable(control_panel)
             .vertical_scroll(Properties::new().width(5).margin(5).scroller_width(5));
         row!(scroll_panel, img_container).into()
@@ -388,99 +809,21 @@ impl Application for Warper {
     }
 }
 
-fn draw(controls: &Controls, img: &DynamicImage) -> Vec<u8> {
-    let opts_theta = NoiseOpts::with_wh(img.width(), img.height())
-        .factor(controls.theta_noise.factor)
-        .y_scale(controls.theta_noise.scale_y)
-        .x_scale(controls.theta_noise.scale_x);
-    let nf_theta = choose_noise(&controls.theta_noise);
-    let opts_r = if controls.sync {
-        let factor = if controls.coordinates == Some(Coordinates::Polar) {
-            30.0 * opts_theta.factor
-        } else {
-            opts_theta.factor
-        };
-        opts_theta.factor(factor)
-    } else {
-        NoiseOpts::with_wh(img.width(), img.height())
-            .factor(controls.radius_noise.factor)
-            .y_scale(controls.radius_noise.scale_y)
-            .x_scale(controls.radius_noise.scale_x)
-    };
-    let nf_r = if controls.sync {
-        let mut tn = controls.theta_noise.clone();
-        tn.img_color_map = Some(img_noise::ColorMap::RedGreen);
-        choose_noise(&tn)
-    } else {
-        choose_noise(&controls.radius_noise).set_seed(98713)
-    };
-
-    let warpx = nf_theta.clone();
-    let warpy = nf_r.clone();
-
-    let warp = match controls.coordinates.unwrap() {
-        Coordinates::Polar => Warp::new(
-            Arc::new(move |z| {
-                pt(
-                    noise2d(&nf_theta, &opts_theta, z.x, z.y),
-                    noise2d_01(&nf_r, &opts_r, z.x, z.y),
-                )
-            }),
-            WarpNode::Img(img, img.width() as f32, img.height() as f32),
-            Coord::Polar,
-        ),
-        Coordinates::Cartesian => Warp::new(
-            Arc::new(move |z| {
-                pt(
-                    noise2d(&nf_theta, &opts_theta, z.x, z.y),
-                    noise2d(&nf_r, &opts_r, z.x, z.y),
-                )
-            }),
-            WarpNode::Img(img, img.width() as f32, img.height() as f32),
-            Coord::Cartesian,
-        ),
-        Coordinates::Absolute => Warp::new(
-            Arc::new(move |z| {
-                pt(
-                    noise2d(&nf_theta, &opts_theta, z.x, z.y),
-                    noise2d(&nf_r, &opts_r, z.x, z.y),
-                )
-            }),
-            WarpNode::Img(img, img.width() as f32, img.height() as f32),
-            Coord::Absolute,
-        ),
-    };
+/// Run the CPU bound `work` on a thread set aside for blocking, so that it
+/// doesn't hold up the executor that runs the other commands.
+async fn blocking<T: Send + 'static>(work: impl FnOnce() -> T + Send + 'static) -> T {
+    tokio::task::spawn_blocking(work).await.unwrap()
+}
 
-    let warp2;
-    if controls.warp_twice {
-        warp2 = Warp::new(
-            Arc::new(move |z| {
-                pt(
-                    noise2d(&warpx, &opts_theta, z.x, z.y),
-                    noise2d(&warpy, &opts_r, z.x, z.y),
-                )
-            }),
-            WarpNode::More(Arc::new(warp)),
-            Coord::Cartesian,
-        );
+/// Downscale the source image to the size used for the interactive preview.
+fn proxy(img: &DynamicImage) -> DynamicImage {
+    if img.width() <= PREVIEW_SIZE && img.height() <= PREVIEW_SIZE {
+        img.clone()
     } else {
-        warp2 = warp;
+        img.resize(PREVIEW_SIZE, PREVIEW_SIZE, imageops::FilterType::Triangle)
     }
+}
 
-    let mut buffer: Vec<(u32, u32)> =
-        Vec::with_capacity(img.width() as usize * img.height() as usize);
-    for i in 0..img.height() {
-        for j in 0..img.width() {
-            buffer.push((j, i));
-        }
-    }
-    let par_iter = buffer.par_iter().flat_map_iter(|p| {
-        let t = warp2
-            .get_wrapped(p.0 as f32, p.1 as f32)
-            .rotate_hue(controls.hue_rotation)
-            .as_u8s();
-        vec![t.0, t.1, t.2, t.3]
-    });
-    let img_data: Vec<u8> = par_iter.collect();
-    img_data
+fn proxy_scale(img: &DynamicImage, proxy: &DynamicImage) -> f32 {
+    proxy.width() as f32 / img.width() as f32
 }
@@ -1,11 +1,16 @@
 #![allow(dead_code)]
 
+use std::f64::consts::TAU;
 use std::marker::PhantomData;
+use std::sync::{Arc, OnceLock};
 
 use crate::gui::lpicklist::LPickList;
 use crate::gui::numeric_input::NumericInput;
-use iced::widget::{text, text_input, Column, Rule};
+use ::noise::core::worley::{distance_functions, worley_2d, worley_3d, worley_4d, ReturnType};
+use ::noise::permutationtable::PermutationTable;
+use iced::widget::{button, row, text, text_input, Column, Rule};
 use iced::Element;
+use serde::{Deserialize, Serialize};
 use wassily::prelude::img_noise::{ColorMap, ImgNoise};
 use wassily::prelude::*;
 
@@ -19,15 +24,31 @@ pub enum NoiseMessage {
     ScaleY(f32),
     Octaves(i32),
     Frequency(f32),
+    Lacunarity(f32),
+    Persistence(f32),
+    Attenuation(f32),
     SinXFreq(f32),
     SinYFreq(f32),
+    OffsetX(f32),
+    OffsetY(f32),
+    Rotation(f32),
+    ShearX(f32),
+    ShearY(f32),
+    Distance(WorleyDistance),
+    Return(WorleyReturn),
+    CheckerSize(i32),
+    Base(BaseNoise),
     ImgNoisePathSet(String),
     ImgNoisePath,
     ImgColorMap(ColorMap),
+    SeedSet(String),
+    Seed,
+    Reroll,
     Null,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct NoiseControls {
     pub function: Option<NoiseFunctionName>,
     pub factor: f32,
@@ -35,19 +56,78 @@ pub struct NoiseControls {
     pub scale_y: f32,
     pub octaves: i32,
     pub frequency: f32,
+    /// How much the frequency grows from one octave to the next.
+    pub lacunarity: f32,
+    /// How much the amplitude shrinks from one octave to the next.
+    pub persistence: f32,
+    /// How strongly each octave of a ridged noise is damped by the one before.
+    pub attenuation: f32,
     pub sin_x_freq: f32,
     pub sin_y_freq: f32,
+    /// Moves the noise, in units of its scale.
+    pub offset_x: f32,
+    pub offset_y: f32,
+    /// Turns the noise about the center of the image, in degrees.
+    pub rotation: f32,
+    pub shear_x: f32,
+    pub shear_y: f32,
+    pub worley_distance: Option<WorleyDistance>,
+    pub worley_return: Option<WorleyReturn>,
+    /// The side of a square of the checkerboard is 2 to this power.
+    pub checker_size: i32,
+    /// The noise that the octaves of a fractal noise are made of.
+    pub base: Option<BaseNoise>,
     pub img_noise_path: String,
-    pub img: DynamicImage,
+    #[serde(skip, default = "default_img")]
+    pub img: Arc<DynamicImage>,
+    #[serde(with = "color_map")]
     pub img_color_map: Option<ColorMap>,
+    pub seed: u32,
+    #[serde(skip)]
     pub dirty: bool,
 }
 
+/// The image used by the image noise until one is loaded, decoded once and
+/// shared by every noise.
+fn default_img() -> Arc<DynamicImage> {
+    static IMG: OnceLock<Arc<DynamicImage>> = OnceLock::new();
+    IMG.get_or_init(|| {
+        Arc::new(DynamicImage::ImageRgba8(
+            ImageBuffer::from_raw(1200, 1000, DEFAULT_IMAGE.to_vec()).unwrap(),
+        ))
+    })
+    .clone()
+}
+
+/// `ColorMap` comes from wassily and isn't serializable, so it is stored by
+/// name.
+mod color_map {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use wassily::prelude::img_noise::ColorMap;
+
+    const COLOR_MAPS: [ColorMap; 3] = [
+        ColorMap::Lightness,
+        ColorMap::RedGreen,
+        ColorMap::YellowBlue,
+    ];
+
+    pub fn serialize<S: Serializer>(map: &Option<ColorMap>, s: S) -> Result<S::Ok, S::Error> {
+        map.map(|map| format!("{map:?}")).serialize(s)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Option<ColorMap>, D::Error> {
+        let name: Option<String> = Option::deserialize(d)?;
+        Ok(name.and_then(|name| {
+            COLOR_MAPS
+                .into_iter()
+                .find(|map| format!("{map:?}") == name)
+        }))
+    }
+}
+
 impl Default for NoiseControls {
     fn default() -> Self {
-        let img = DynamicImage::ImageRgba8(
-            ImageBuffer::from_raw(1200, 1000, DEFAULT_IMAGE.to_vec()).unwrap(),
-        );
+        let img = default_img();
         Self {
             function: Some(NoiseFunctionName::Fbm),
             factor: 50.0,
@@ -55,17 +135,40 @@ impl Default for NoiseControls {
             scale_y: 8.0,
             octaves: 1,
             frequency: 1.0,
+            // The lacunarity of the fractal noises before it could be set.
+            lacunarity: (std::f64::consts::PI * 2.0 / 3.0) as f32,
+            persistence: 0.5,
+            attenuation: 2.0,
             sin_x_freq: 1.0,
             sin_y_freq: 1.0,
+            offset_x: 0.0,
+            offset_y: 0.0,
+            rotation: 0.0,
+            shear_x: 0.0,
+            shear_y: 0.0,
+            worley_distance: Some(WorleyDistance::Euclidean),
+            worley_return: Some(WorleyReturn::Value),
+            checker_size: 0,
+            base: Some(BaseNoise::Perlin),
             img_noise_path: String::from(""),
             img,
             img_color_map: Some(ColorMap::Lightness),
+            seed: 0,
             dirty: false,
         }
     }
 }
 
 impl<'a> NoiseControls {
+    /// Read the image used by the image noise from `img_noise_path`.
+    pub fn load_img(&mut self) {
+        self.img = match open(std::path::Path::new(&self.img_noise_path)) {
+            Ok(img) => Arc::new(img),
+            Err(_) => default_img(),
+        };
+    }
+
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         function: Option<NoiseFunctionName>,
         factor: f32,
@@ -73,11 +176,24 @@ impl<'a> NoiseControls {
         scale_y: f32,
         octaves: i32,
         frequency: f32,
+        lacunarity: f32,
+        persistence: f32,
+        attenuation: f32,
         sin_x_freq: f32,
         sin_y_freq: f32,
+        offset_x: f32,
+        offset_y: f32,
+        rotation: f32,
+        shear_x: f32,
+        shear_y: f32,
+        worley_distance: Option<WorleyDistance>,
+        worley_return: Option<WorleyReturn>,
+        checker_size: i32,
+        base: Option<BaseNoise>,
         img_noise_path: String,
         img: DynamicImage,
         img_color_map: Option<ColorMap>,
+        seed: u32,
         dirty: bool,
     ) -> Self {
         Self {
@@ -87,11 +203,24 @@ impl<'a> NoiseControls {
             scale_y,
             octaves,
             frequency,
+            lacunarity,
+            persistence,
+            attenuation,
             sin_x_freq,
             sin_y_freq,
+            offset_x,
+            offset_y,
+            rotation,
+            shear_x,
+            shear_y,
+            worley_distance,
+            worley_return,
+            checker_size,
+            base,
             img_noise_path,
-            img,
+            img: Arc::new(img),
             img_color_map,
+            seed,
             dirty,
         }
     }
@@ -126,6 +255,21 @@ impl<'a> NoiseControls {
         self
     }
 
+    pub fn set_lacunarity(mut self, lacunarity: f32) -> Self {
+        self.lacunarity = lacunarity;
+        self
+    }
+
+    pub fn set_persistence(mut self, persistence: f32) -> Self {
+        self.persistence = persistence;
+        self
+    }
+
+    pub fn set_attenuation(mut self, attenuation: f32) -> Self {
+        self.attenuation = attenuation;
+        self
+    }
+
     pub fn set_sin_x_freq(mut self, sin_x_freq: f32) -> Self {
         self.sin_x_freq = sin_x_freq;
         self
@@ -136,61 +280,152 @@ impl<'a> NoiseControls {
         self
     }
 
+    pub fn set_offset(mut self, offset_x: f32, offset_y: f32) -> Self {
+        self.offset_x = offset_x;
+        self.offset_y = offset_y;
+        self
+    }
+
+    pub fn set_rotation(mut self, rotation: f32) -> Self {
+        self.rotation = rotation;
+        self
+    }
+
+    pub fn set_shear(mut self, shear_x: f32, shear_y: f32) -> Self {
+        self.shear_x = shear_x;
+        self.shear_y = shear_y;
+        self
+    }
+
+    pub fn set_worley(mut self, distance: WorleyDistance, ret: WorleyReturn) -> Self {
+        self.worley_distance = Some(distance);
+        self.worley_return = Some(ret);
+        self
+    }
+
+    pub fn set_checker_size(mut self, checker_size: i32) -> Self {
+        self.checker_size = checker_size;
+        self
+    }
+
+    pub fn set_base(mut self, base: BaseNoise) -> Self {
+        self.base = Some(base);
+        self
+    }
+
     pub fn set_img_noise_path(mut self, img_noise_path: String) -> Self {
         self.img_noise_path = img_noise_path;
         self
     }
 
+    pub fn set_seed(mut self, seed: u32) -> Self {
+        self.seed = seed;
+        self
+    }
+
     pub fn update(&mut self, message: NoiseMessage) {
         use NoiseMessage::*;
         self.dirty = true;
         match message {
             Function(n) => {
+                // Keep a persistence that was changed from the default of the
+                // fractal it was set for, a function that isn't fractal has
+                // none to keep.
+                let untouched = self
+                    .function
+                    .and_then(|f| f.persistence())
+                    .unwrap_or(self.persistence);
                 self.function = Some(n);
                 if n == NoiseFunctionName::Image {
                     self.scale_x = 1.0;
                     self.scale_y = 1.0;
                 }
+                if let Some(p) = n.persistence() {
+                    if self.persistence == untouched {
+                        self.persistence = p;
+                    }
+                }
             }
             Factor(f) => self.factor = f,
             ScaleX(s) => self.scale_x = s,
             ScaleY(s) => self.scale_y = s,
             Octaves(octaves) => self.octaves = octaves,
             Frequency(frequency) => self.frequency = frequency,
+            Lacunarity(lacunarity) => self.lacunarity = lacunarity,
+            Persistence(persistence) => self.persistence = persistence,
+            Attenuation(attenuation) => self.attenuation = attenuation,
             SinXFreq(sin_x_freq) => self.sin_x_freq = sin_x_freq,
             SinYFreq(sin_y_freq) => self.sin_y_freq = sin_y_freq,
+            OffsetX(x) => self.offset_x = x,
+            OffsetY(y) => self.offset_y = y,
+            Rotation(r) => self.rotation = r,
+            ShearX(s) => self.shear_x = s,
+            ShearY(s) => self.shear_y = s,
+            Distance(d) => self.worley_distance = Some(d),
+            Return(r) => self.worley_return = Some(r),
+            CheckerSize(s) => self.checker_size = s,
+            Base(b) => self.base = Some(b),
             ImgNoisePathSet(img_noise_path) => {
                 self.img_noise_path = img_noise_path;
                 self.dirty = false
             }
-            ImgNoisePath => {
-                self.img = match open(std::path::Path::new(&self.img_noise_path)) {
-                    Ok(img) => img,
-                    Err(_) => DynamicImage::ImageRgba8(
-                        ImageBuffer::from_raw(1200, 1000, DEFAULT_IMAGE.to_vec()).unwrap(),
-                    ),
-                };
-            }
+            ImgNoisePath => self.load_img(),
             ImgColorMap(cm) => {
                 self.img_color_map = Some(cm);
             }
+            SeedSet(s) => {
+                if s.is_empty() {
+                    self.seed = 0;
+                } else if let Ok(seed) = s.parse() {
+                    self.seed = seed;
+                }
+                self.dirty = false;
+            }
+            Seed => {}
+            Reroll => self.seed = rand::random(),
             Null => {}
         }
     }
 
-    pub fn view(&self) -> Element<'a, NoiseMessage> {
+    /// The controls of the noise, with fractal noises of up to `max_octaves`.
+    pub fn view(&self, max_octaves: i32) -> Element<'a, NoiseMessage> {
         use NoiseFunctionName::*;
         use NoiseMessage::*;
         let mut col = Column::new().push(Rule::horizontal(5));
         col = col.push(LPickList::new(
             "Noise Function".to_string(),
             vec![
-                Fbm, Billow, Ridged, Value, Cylinders, Curl, Sinusoidal, SinFbm, Image,
+                Fbm,
+                Billow,
+                Ridged,
+                HybridMulti,
+                BasicMulti,
+                Value,
+                OpenSimplex,
+                SuperSimplex,
+                Worley,
+                Checkerboard,
+                Cylinders,
+                Curl,
+                Sinusoidal,
+                SinFbm,
+                Image,
             ],
             self.function,
             |x| x.map_or(Null, Function),
         ));
-        let func = self.function.expect("Noise function not set");
+        col = col.push(text("Seed").size(15)).push(
+            row!(
+                text_input("", &self.seed.to_string())
+                    .on_input(SeedSet)
+                    .size(15)
+                    .width(110)
+                    .on_submit(Seed),
+                button(text("Reroll").size(15)).on_press(Reroll)
+            )
+            .spacing(10),
+        );
+        let func = self.function.unwrap_or(Fbm);
         if func == Image {
             col = col
                 .push(text("Image Path").width(200))
@@ -231,6 +466,47 @@ impl<'a> NoiseControls {
                     ScaleY,
                 ))
         }
+        col = col
+            .push(NumericInput::new(
+                "Offset X".to_string(),
+                self.offset_x,
+                -50.0..=50.0,
+                0.05,
+                2,
+                OffsetX,
+            ))
+            .push(NumericInput::new(
+                "Offset Y".to_string(),
+                self.offset_y,
+                -50.0..=50.0,
+                0.05,
+                2,
+                OffsetY,
+            ))
+            .push(NumericInput::new(
+                "Rotation".to_string(),
+                self.rotation,
+                -180.0..=180.0,
+                1.0,
+                0,
+                Rotation,
+            ))
+            .push(NumericInput::new(
+                "Shear X".to_string(),
+                self.shear_x,
+                -2.0..=2.0,
+                0.05,
+                2,
+                ShearX,
+            ))
+            .push(NumericInput::new(
+                "Shear Y".to_string(),
+                self.shear_y,
+                -2.0..=2.0,
+                0.05,
+                2,
+                ShearY,
+            ));
         col = col.push(NumericInput::new(
             "Noise Factor".to_string(),
             self.factor,
@@ -258,31 +534,101 @@ impl<'a> NoiseControls {
                     SinYFreq,
                 ));
         }
-        if func == Fbm || func == Ridged || func == Billow || func == Curl || func == SinFbm {
+        if func == Worley {
+            col = col
+                .push(LPickList::new(
+                    "Distance".to_string(),
+                    vec![
+                        WorleyDistance::Euclidean,
+                        WorleyDistance::EuclideanSquared,
+                        WorleyDistance::Manhattan,
+                        WorleyDistance::Chebyshev,
+                    ],
+                    self.worley_distance,
+                    |x| x.map_or(Null, Distance),
+                ))
+                .push(LPickList::new(
+                    "Return".to_string(),
+                    vec![WorleyReturn::Value, WorleyReturn::Distance],
+                    self.worley_return,
+                    |x| x.map_or(Null, Return),
+                ));
+        }
+        if func == Checkerboard {
             col = col.push(NumericInput::new(
-                "Octaves".to_string(),
-                self.octaves,
-                1..=6,
+                "Checker Size".to_string(),
+                self.checker_size,
+                0..=5,
                 1,
                 0,
-                Octaves,
+                CheckerSize,
             ));
-            if self.octaves > 1 {
-                col = col.push(NumericInput::new(
-                    "Frequency".to_string(),
-                    self.frequency,
-                    0.1..=4.00,
-                    0.1,
-                    1,
-                    Frequency,
+        }
+        if func.is_fractal() {
+            col = col
+                .push(LPickList::new(
+                    "Base Noise".to_string(),
+                    vec![
+                        BaseNoise::Perlin,
+                        BaseNoise::OpenSimplex,
+                        BaseNoise::SuperSimplex,
+                        BaseNoise::Value,
+                        BaseNoise::Worley,
+                    ],
+                    self.base,
+                    |x| x.map_or(Null, Base),
                 ))
+                .push(NumericInput::new(
+                    "Octaves".to_string(),
+                    self.octaves,
+                    1..=max_octaves,
+                    1,
+                    0,
+                    Octaves,
+                ));
+            if self.octaves > 1 {
+                col = col
+                    .push(NumericInput::new(
+                        "Frequency".to_string(),
+                        self.frequency,
+                        0.1..=4.00,
+                        0.1,
+                        1,
+                        Frequency,
+                    ))
+                    .push(NumericInput::new(
+                        "Lacunarity".to_string(),
+                        self.lacunarity,
+                        1.0..=4.0,
+                        0.05,
+                        2,
+                        Lacunarity,
+                    ))
+                    .push(NumericInput::new(
+                        "Persistence".to_string(),
+                        self.persistence,
+                        0.05..=1.0,
+                        0.05,
+                        2,
+                        Persistence,
+                    ));
+                if func == Ridged {
+                    col = col.push(NumericInput::new(
+                        "Attenuation".to_string(),
+                        self.attenuation,
+                        0.5..=4.0,
+                        0.1,
+                        1,
+                        Attenuation,
+                    ));
+                }
             }
         }
         col.spacing(7).into()
     }
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum NoiseFunctionName {
     Fbm,
     Billow,
@@ -293,6 +639,34 @@ pub enum NoiseFunctionName {
     Sinusoidal,
     SinFbm,
     Image,
+    OpenSimplex,
+    SuperSimplex,
+    Worley,
+    Checkerboard,
+    HybridMulti,
+    BasicMulti,
+}
+
+impl NoiseFunctionName {
+    /// True for the noises made of octaves of a simpler noise.
+    pub fn is_fractal(&self) -> bool {
+        use NoiseFunctionName::*;
+        matches!(
+            self,
+            Fbm | Billow | Ridged | HybridMulti | BasicMulti | Curl | SinFbm
+        )
+    }
+
+    /// The persistence that a fractal noise starts with.
+    pub fn persistence(&self) -> Option<f32> {
+        use NoiseFunctionName::*;
+        match self {
+            Ridged => Some(1.0),
+            HybridMulti => Some(0.25),
+            f if f.is_fractal() => Some(0.5),
+            _ => None,
+        }
+    }
 }
 
 impl std::fmt::Display for NoiseFunctionName {
@@ -310,31 +684,460 @@ impl std::fmt::Display for NoiseFunctionName {
                 NoiseFunctionName::Sinusoidal => "Sinusoidal",
                 NoiseFunctionName::SinFbm => "SinFbm",
                 NoiseFunctionName::Image => "Image",
+                NoiseFunctionName::OpenSimplex => "OpenSimplex",
+                NoiseFunctionName::SuperSimplex => "SuperSimplex",
+                NoiseFunctionName::Worley => "Worley",
+                NoiseFunctionName::Checkerboard => "Checkerboard",
+                NoiseFunctionName::HybridMulti => "HybridMulti",
+                NoiseFunctionName::BasicMulti => "BasicMulti",
+            }
+        )
+    }
+}
+
+/// The noise that the octaves of a fractal noise are made of.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum BaseNoise {
+    Perlin,
+    OpenSimplex,
+    SuperSimplex,
+    Value,
+    Worley,
+}
+
+impl std::fmt::Display for BaseNoise {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                BaseNoise::Perlin => "Perlin",
+                BaseNoise::OpenSimplex => "OpenSimplex",
+                BaseNoise::SuperSimplex => "SuperSimplex",
+                BaseNoise::Value => "Value",
+                BaseNoise::Worley => "Worley",
+            }
+        )
+    }
+}
+
+/// How the distance to the nearest cell of a Worley noise is measured.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum WorleyDistance {
+    Euclidean,
+    EuclideanSquared,
+    Manhattan,
+    Chebyshev,
+}
+
+impl std::fmt::Display for WorleyDistance {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                WorleyDistance::Euclidean => "Euclidean",
+                WorleyDistance::EuclideanSquared => "Euclidean Squared",
+                WorleyDistance::Manhattan => "Manhattan",
+                WorleyDistance::Chebyshev => "Chebyshev",
+            }
+        )
+    }
+}
+
+/// Whether a Worley noise is flat over each cell or grows with the distance
+/// to the nearest cell.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum WorleyReturn {
+    Value,
+    Distance,
+}
+
+impl std::fmt::Display for WorleyReturn {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                WorleyReturn::Value => "Value",
+                WorleyReturn::Distance => "Distance",
             }
         )
     }
 }
 
+/// Worley noise measuring distance with `distance` and returning `ret`. Unlike
+/// `Worley`, which keeps its distance function in an `Rc`, it can be shared by
+/// the threads of a render.
+#[derive(Clone, Debug)]
+pub struct Cells {
+    distance: WorleyDistance,
+    ret: WorleyReturn,
+    seed: u32,
+    perm_table: PermutationTable,
+}
+
+impl Cells {
+    pub fn new(distance: WorleyDistance, ret: WorleyReturn) -> Self {
+        Self {
+            distance,
+            ret,
+            seed: 0,
+            perm_table: PermutationTable::new(0),
+        }
+    }
+
+    fn measure(&self) -> fn(&[f64], &[f64]) -> f64 {
+        match self.distance {
+            WorleyDistance::Euclidean => distance_functions::euclidean,
+            WorleyDistance::EuclideanSquared => distance_functions::euclidean_squared,
+            WorleyDistance::Manhattan => distance_functions::manhattan,
+            WorleyDistance::Chebyshev => distance_functions::chebyshev,
+        }
+    }
+
+    fn return_type(&self) -> ReturnType {
+        match self.ret {
+            WorleyReturn::Value => ReturnType::Value,
+            WorleyReturn::Distance => ReturnType::Distance,
+        }
+    }
+}
+
+/// The octaves of a fractal noise are built from the default, the Euclidean
+/// distance and the value of the cell.
+impl Default for Cells {
+    fn default() -> Self {
+        Self::new(WorleyDistance::Euclidean, WorleyReturn::Value)
+    }
+}
+
+impl Seedable for Cells {
+    fn set_seed(self, seed: u32) -> Self {
+        Self {
+            seed,
+            perm_table: PermutationTable::new(seed),
+            ..self
+        }
+    }
+
+    fn seed(&self) -> u32 {
+        self.seed
+    }
+}
+
+impl NoiseFn<f64, 2> for Cells {
+    fn get(&self, point: [f64; 2]) -> f64 {
+        worley_2d(&self.perm_table, self.measure(), self.return_type(), point)
+    }
+}
+
+impl NoiseFn<f64, 3> for Cells {
+    fn get(&self, point: [f64; 3]) -> f64 {
+        worley_3d(&self.perm_table, self.measure(), self.return_type(), point)
+    }
+}
+
+impl NoiseFn<f64, 4> for Cells {
+    fn get(&self, point: [f64; 4]) -> f64 {
+        worley_4d(&self.perm_table, self.measure(), self.return_type(), point)
+    }
+}
+
+/// Where the plane that a warp samples sits in the domain of a noise.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Lift {
+    /// The plane itself, nothing moves.
+    Plane,
+    /// The slice of 3D noise at a moment in time. Even at time 0 it is not
+    /// the noise of the plane, the 2D and 3D noises differ.
+    Time(f64),
+    /// A slice of 4D noise whose position goes around a circle of `radius`
+    /// in the last two dimensions, so it comes back to where it started.
+    Loop { angle: f64, radius: f64 },
+}
+
+impl Lift {
+    /// How far the patterns that move by translation have moved. In time
+    /// they move along the x axis, a loop moves them around a circle that
+    /// starts and ends where they are still.
+    pub fn shift(&self) -> [f64; 2] {
+        match *self {
+            Lift::Plane => [0.0; 2],
+            Lift::Time(t) => [t, 0.0],
+            Lift::Loop { angle, radius } => [radius * angle.cos() - radius, radius * angle.sin()],
+        }
+    }
+
+    /// The phase of the sines, which only ever grows. A loop goes through a
+    /// whole number of turns, as many as fit in the distance it travels.
+    pub fn phase(&self) -> f64 {
+        match *self {
+            Lift::Plane => 0.0,
+            Lift::Time(t) => t,
+            Lift::Loop { angle, radius } => angle * radius.round().max(1.0),
+        }
+    }
+
+    /// Where a torus is moved to in 4D, a loop moves it around a circle.
+    fn offset(&self) -> [f64; 4] {
+        match *self {
+            Lift::Plane => [0.0; 4],
+            Lift::Time(t) => [t; 4],
+            Lift::Loop { angle, radius } => {
+                let (c, s) = (radius * angle.cos(), radius * angle.sin());
+                [c, s, c, s]
+            }
+        }
+    }
+}
+
+type Boxed<const N: usize> = Arc<dyn NoiseFn<f64, N> + Send + Sync>;
+
+/// A noise boxed for each of the dimensions that a `Domain` samples it in, so
+/// that noises of different types can stand behind one. SuperSimplex has no
+/// 4D version, it is `flat`.
+#[derive(Clone)]
+pub struct Dims {
+    plane: Boxed<2>,
+    time: Boxed<3>,
+    space: Option<Boxed<4>>,
+    seed: u32,
+    /// Builds the same noise with another seed.
+    reseed: Arc<dyn Fn(u32) -> Dims + Send + Sync>,
+}
+
+impl Dims {
+    pub fn new<S>(source: S) -> Self
+    where
+        S: NoiseFn<f64, 2>
+            + NoiseFn<f64, 3>
+            + NoiseFn<f64, 4>
+            + Seedable
+            + Clone
+            + Send
+            + Sync
+            + 'static,
+    {
+        let seed = source.seed();
+        let source = Arc::new(source);
+        let unseeded = source.clone();
+        Self {
+            plane: source.clone(),
+            time: source.clone(),
+            space: Some(source),
+            seed,
+            reseed: Arc::new(move |seed| Dims::new(S::clone(&unseeded).set_seed(seed))),
+        }
+    }
+
+    /// A noise with no 4D version.
+    pub fn flat<S>(source: S) -> Self
+    where
+        S: NoiseFn<f64, 2> + NoiseFn<f64, 3> + Seedable + Clone + Send + Sync + 'static,
+    {
+        let seed = source.seed();
+        let source = Arc::new(source);
+        let unseeded = source.clone();
+        Self {
+            plane: source.clone(),
+            time: source,
+            space: None,
+            seed,
+            reseed: Arc::new(move |seed| Dims::flat(S::clone(&unseeded).set_seed(seed))),
+        }
+    }
+}
+
+impl Seedable for Dims {
+    fn set_seed(self, seed: u32) -> Self {
+        (self.reseed)(seed)
+    }
+
+    fn seed(&self) -> u32 {
+        self.seed
+    }
+}
+
+/// Samples a noise of higher dimension on the plane given by a `Lift`. With a
+/// `period` the plane is wrapped around a torus in 4D instead, so the noise
+/// repeats every `period` along each axis. Time then moves the torus. A flat
+/// noise can't be wrapped, and it drifts around a loop instead of moving
+/// through 4D.
+#[derive(Clone)]
+pub struct Domain {
+    pub source: Dims,
+    pub lift: Lift,
+    pub period: Option<[f64; 2]>,
+}
+
+impl Domain {
+    pub fn new(source: Dims, lift: Lift, period: Option<[f64; 2]>) -> Self {
+        Self {
+            source,
+            lift,
+            period,
+        }
+    }
+}
+
+impl Seedable for Domain {
+    fn set_seed(self, seed: u32) -> Self {
+        Self {
+            source: self.source.set_seed(seed),
+            ..self
+        }
+    }
+
+    fn seed(&self) -> u32 {
+        self.source.seed()
+    }
+}
+
+impl NoiseFn<f64, 2> for Domain {
+    fn get(&self, point: [f64; 2]) -> f64 {
+        let Dims { plane, time, space } = &self.source;
+        if let (Some([px, py]), Some(space)) = (self.period, space) {
+            let (a, b) = (TAU * point[0] / px, TAU * point[1] / py);
+            let (rx, ry) = (px / TAU, py / TAU);
+            let o = self.lift.offset();
+            return space.get([
+                o[0] + rx * a.cos(),
+                o[1] + rx * a.sin(),
+                o[2] + ry * b.cos(),
+                o[3] + ry * b.sin(),
+            ]);
+        }
+        match (self.lift, space) {
+            (Lift::Plane, _) => plane.get(point),
+            (Lift::Time(t), _) => time.get([point[0], point[1], t]),
+            (Lift::Loop { angle, radius }, Some(space)) => space.get([
+                point[0],
+                point[1],
+                radius * angle.cos(),
+                radius * angle.sin(),
+            ]),
+            (Lift::Loop { .. }, None) => {
+                let [dx, dy] = self.lift.shift();
+                plane.get([point[0] + dx, point[1] + dy])
+            }
+        }
+    }
+}
+
+/// A fractal noise that can be built on any of the base noises.
+pub trait Fractal {
+    /// The fractal on the base noise of `controls`, Perlin when there is none.
+    fn build(controls: &NoiseControls) -> Dims;
+}
+
+pub type AnyFbm = (
+    Fbm<Perlin>,
+    Fbm<OpenSimplex>,
+    Fbm<SuperSimplex>,
+    Fbm<Value>,
+    Fbm<Cells>,
+);
+pub type AnyBillow = (
+    Billow<Perlin>,
+    Billow<OpenSimplex>,
+    Billow<SuperSimplex>,
+    Billow<Value>,
+    Billow<Cells>,
+);
+pub type AnyRidged = (
+    RidgedMulti<Perlin>,
+    RidgedMulti<OpenSimplex>,
+    RidgedMulti<SuperSimplex>,
+    RidgedMulti<Value>,
+    RidgedMulti<Cells>,
+);
+pub type AnyHybridMulti = (
+    HybridMulti<Perlin>,
+    HybridMulti<OpenSimplex>,
+    HybridMulti<SuperSimplex>,
+    HybridMulti<Value>,
+    HybridMulti<Cells>,
+);
+pub type AnyBasicMulti = (
+    BasicMulti<Perlin>,
+    BasicMulti<OpenSimplex>,
+    BasicMulti<SuperSimplex>,
+    BasicMulti<Value>,
+    BasicMulti<Cells>,
+);
+
+/// `P` on Perlin, `O` on OpenSimplex and so on. SuperSimplex has no 4D, so it
+/// can't loop through time.
+impl<P, O, S, V, W> Fractal for (P, O, S, V, W)
+where
+    P: Layers + NoiseFn<f64, 2> + NoiseFn<f64, 3> + NoiseFn<f64, 4> + Send + Sync + 'static,
+    O: Layers + NoiseFn<f64, 2> + NoiseFn<f64, 3> + NoiseFn<f64, 4> + Send + Sync + 'static,
+    S: Layers + NoiseFn<f64, 2> + NoiseFn<f64, 3> + Send + Sync + 'static,
+    V: Layers + NoiseFn<f64, 2> + NoiseFn<f64, 3> + NoiseFn<f64, 4> + Send + Sync + 'static,
+    W: Layers + NoiseFn<f64, 2> + NoiseFn<f64, 3> + NoiseFn<f64, 4> + Send + Sync + 'static,
+{
+    fn build(controls: &NoiseControls) -> Dims {
+        match controls.base.unwrap_or(BaseNoise::Perlin) {
+            BaseNoise::Perlin => Dims::new(P::layers(controls)),
+            BaseNoise::OpenSimplex => Dims::new(O::layers(controls)),
+            BaseNoise::SuperSimplex => Dims::flat(S::layers(controls)),
+            BaseNoise::Value => Dims::new(V::layers(controls)),
+            BaseNoise::Worley => Dims::new(W::layers(controls)),
+        }
+    }
+}
+
+/// A fractal noise set up from the controls. A Worley base uses the Euclidean
+/// distance and returns the value of the cell.
+pub trait Layers: MultiFractal + Seedable + Default + Clone {
+    fn layers(controls: &NoiseControls) -> Self {
+        layers(Self::default(), controls)
+    }
+}
+
+impl<T: Default + Seedable + Clone> Layers for Fbm<T> {}
+impl<T: Default + Seedable + Clone> Layers for Billow<T> {}
+impl<T: Default + Seedable + Clone> Layers for HybridMulti<T> {}
+impl<T: Default + Seedable + Clone> Layers for BasicMulti<T> {}
+
+impl<T: Default + Seedable + Clone> Layers for RidgedMulti<T> {
+    fn layers(controls: &NoiseControls) -> Self {
+        layers(Self::default(), controls).set_attenuation(controls.attenuation as f64)
+    }
+}
+
+/// `noise` with the seed, octaves, frequency, lacunarity and persistence of
+/// `controls`.
+fn layers<F: MultiFractal + Seedable>(noise: F, controls: &NoiseControls) -> F {
+    noise
+        .set_octaves(controls.octaves as usize)
+        .set_frequency(controls.frequency as f64)
+        .set_lacunarity(controls.lacunarity as f64)
+        .set_persistence(controls.persistence as f64)
+        .set_seed(controls.seed)
+}
+
+/// The fractal, simplex and Worley noises evolve through a third dimension,
+/// `Curl` and `SinFbm` through the `Fbm` they are built on. `Cylinders` and
+/// `Checkerboard` drift and `Sinusoidal` changes phase, the image noise doesn't
+/// move. Each noise is seeded when it is built, and reports its seed.
 #[derive(Clone)]
 pub enum NoiseFunction {
-    Fbm(Fbm<Perlin>),
-    Billow(Billow<Perlin>),
-    Ridged(RidgedMulti<Perlin>),
-    Value(Value),
-    Cylinders(TranslatePoint<Cylinders>),
-    Curl(Curl<Fbm<Perlin>>),
+    Lifted(Domain),
+    Checkerboard(Shift<TranslatePoint<Checkerboard>>),
+    Cylinders(Shift<TranslatePoint<Cylinders>>),
+    Curl(Curl<Domain>),
     Sinusoidal(Sinusoidal),
-    SinFbm(Sin<f64, Fbm<Perlin>, 2>),
-    Image(ImgNoise),
+    SinFbm(Sin<f64, Domain, 2>),
+    Image(Fold<ImgNoise>),
 }
 
 impl NoiseFn<f64, 2> for NoiseFunction {
     fn get(&self, point: [f64; 2]) -> f64 {
         match self {
-            NoiseFunction::Fbm(n) => n.get(point),
-            NoiseFunction::Billow(n) => n.get(point),
-            NoiseFunction::Ridged(n) => n.get(point),
-            NoiseFunction::Value(n) => n.get(point),
+            NoiseFunction::Lifted(n) => n.get(point),
+            NoiseFunction::Checkerboard(n) => n.get(point),
             NoiseFunction::Cylinders(n) => n.get(point),
             NoiseFunction::Curl(n) => n.get(point),
             NoiseFunction::Sinusoidal(n) => n.get(point),
@@ -347,69 +1150,313 @@ impl NoiseFn<f64, 2> for NoiseFunction {
 impl Seedable for NoiseFunction {
     fn set_seed(self, seed: u32) -> Self {
         match self {
-            NoiseFunction::Fbm(n) => NoiseFunction::Fbm(n.set_seed(seed)),
-            NoiseFunction::Billow(n) => NoiseFunction::Billow(n.set_seed(seed)),
-            NoiseFunction::Ridged(n) => NoiseFunction::Ridged(n.set_seed(seed)),
-            NoiseFunction::Value(n) => NoiseFunction::Value(n.set_seed(seed)),
-            NoiseFunction::Cylinders(n) => NoiseFunction::Cylinders(n),
+            NoiseFunction::Lifted(n) => NoiseFunction::Lifted(n.set_seed(seed)),
+            NoiseFunction::Checkerboard(n) => NoiseFunction::Checkerboard(n.set_seed(seed)),
+            NoiseFunction::Cylinders(n) => NoiseFunction::Cylinders(n.set_seed(seed)),
             NoiseFunction::Curl(n) => NoiseFunction::Curl(n.set_seed(seed)),
-            NoiseFunction::Sinusoidal(n) => NoiseFunction::Sinusoidal(n),
-            NoiseFunction::SinFbm(n) => NoiseFunction::SinFbm(n),
-            NoiseFunction::Image(n) => NoiseFunction::Image(n),
+            NoiseFunction::Sinusoidal(n) => NoiseFunction::Sinusoidal(n.set_seed(seed)),
+            NoiseFunction::SinFbm(n) => NoiseFunction::SinFbm(Sin::new(n.source.set_seed(seed))),
+            NoiseFunction::Image(n) => NoiseFunction::Image(n.set_seed(seed)),
+        }
+    }
+
+    fn seed(&self) -> u32 {
+        match self {
+            NoiseFunction::Lifted(n) => n.seed(),
+            NoiseFunction::Checkerboard(n) => n.seed(),
+            NoiseFunction::Cylinders(n) => n.seed(),
+            NoiseFunction::Curl(n) => n.seed(),
+            NoiseFunction::Sinusoidal(n) => n.seed(),
+            NoiseFunction::SinFbm(n) => n.source.seed(),
+            NoiseFunction::Image(n) => n.seed(),
+        }
+    }
+}
+
+/// Two numbers in `0..1` picked by `seed`, both 0 for seed 0 so that the
+/// default seed leaves a noise as it is.
+fn seed_unit(seed: u32) -> [f64; 2] {
+    if seed == 0 {
+        return [0.0; 2];
+    }
+    let mut rng = SmallRng::seed_from_u64(seed as u64);
+    [rng.gen(), rng.gen()]
+}
+
+/// Gives a seed to a noise that has none of its own by moving its domain up to
+/// `spread` along each axis, to a point picked by the seed.
+#[derive(Clone, Debug)]
+pub struct Shift<S> {
+    pub source: S,
+    spread: f64,
+    seed: u32,
+    offset: [f64; 2],
+}
+
+impl<S> Shift<S> {
+    pub fn new(source: S, spread: f64) -> Self {
+        Self {
+            source,
+            spread,
+            seed: 0,
+            offset: [0.0; 2],
+        }
+    }
+}
+
+impl<S: NoiseFn<f64, 2>> NoiseFn<f64, 2> for Shift<S> {
+    fn get(&self, point: [f64; 2]) -> f64 {
+        self.source
+            .get([point[0] + self.offset[0], point[1] + self.offset[1]])
+    }
+}
+
+impl<S> Seedable for Shift<S> {
+    fn set_seed(self, seed: u32) -> Self {
+        let offset = seed_unit(seed).map(|u| self.spread * u);
+        Self {
+            seed,
+            offset,
+            ..self
         }
     }
 
     fn seed(&self) -> u32 {
-        todo!()
+        self.seed
     }
 }
 
-pub fn choose_noise(controls: &NoiseControls) -> NoiseFunction {
-    match controls.function.unwrap() {
-        NoiseFunctionName::Fbm => NoiseFunction::Fbm(
-            Fbm::<Perlin>::default()
-                .set_octaves(controls.octaves as usize)
-                .set_frequency(controls.frequency as f64),
+/// Gives a seed to the image noise, whose domain is the image itself, by
+/// moving its domain to a point picked by the seed. Points that move past the
+/// `extent` of the image are reflected back into it, so the noise stays
+/// continuous.
+#[derive(Clone, Debug)]
+pub struct Fold<S> {
+    pub source: S,
+    extent: [f64; 2],
+    seed: u32,
+    offset: [f64; 2],
+}
+
+impl<S> Fold<S> {
+    pub fn new(source: S, extent: [f64; 2]) -> Self {
+        Self {
+            source,
+            extent,
+            seed: 0,
+            offset: [0.0; 2],
+        }
+    }
+}
+
+impl<S: NoiseFn<f64, 2>> NoiseFn<f64, 2> for Fold<S> {
+    fn get(&self, point: [f64; 2]) -> f64 {
+        let fold = |x: f64, n: f64| {
+            let x = x.rem_euclid(2.0 * n);
+            if x < n {
+                x
+            } else {
+                2.0 * n - x
+            }
+        };
+        self.source.get([
+            fold(point[0] + self.offset[0], self.extent[0]),
+            fold(point[1] + self.offset[1], self.extent[1]),
+        ])
+    }
+}
+
+impl<S> Seedable for Fold<S> {
+    fn set_seed(self, seed: u32) -> Self {
+        let [u, v] = seed_unit(seed);
+        Self {
+            seed,
+            offset: [2.0 * self.extent[0] * u, 2.0 * self.extent[1] * v],
+            ..self
+        }
+    }
+
+    fn seed(&self) -> u32 {
+        self.seed
+    }
+}
+
+/// Shears, rotates and then moves the domain of a noise. The rotation and the
+/// shear are about `center`, the middle of the image in the domain of the noise.
+#[derive(Clone, Debug)]
+pub struct Affine<S> {
+    pub source: S,
+    center: [f64; 2],
+    matrix: [[f64; 2]; 2],
+    offset: [f64; 2],
+}
+
+impl<S> Affine<S> {
+    pub fn new(source: S, center: [f64; 2]) -> Self {
+        Self {
+            source,
+            center,
+            matrix: [[1.0, 0.0], [0.0, 1.0]],
+            offset: [0.0; 2],
+        }
+    }
+
+    /// Rotate by `angle` in degrees after a shear of `shear_x` along x and
+    /// `shear_y` along y.
+    pub fn set_linear(self, angle: f64, shear_x: f64, shear_y: f64) -> Self {
+        let (s, c) = angle.to_radians().sin_cos();
+        let matrix = [
+            [c - s * shear_y, c * shear_x - s],
+            [s + c * shear_y, s * shear_x + c],
+        ];
+        Self { matrix, ..self }
+    }
+
+    pub fn set_offset(self, x: f64, y: f64) -> Self {
+        Self {
+            offset: [x, y],
+            ..self
+        }
+    }
+
+    /// True when the domain is left as it is.
+    pub fn is_identity(&self) -> bool {
+        self.matrix == [[1.0, 0.0], [0.0, 1.0]] && self.offset == [0.0; 2]
+    }
+}
+
+impl<S: Seedable> Seedable for Affine<S> {
+    fn set_seed(self, seed: u32) -> Self {
+        Self {
+            source: self.source.set_seed(seed),
+            ..self
+        }
+    }
+
+    fn seed(&self) -> u32 {
+        self.source.seed()
+    }
+}
+
+impl<S: NoiseFn<f64, 2>> NoiseFn<f64, 2> for Affine<S> {
+    fn get(&self, point: [f64; 2]) -> f64 {
+        if self.is_identity() {
+            return self.source.get(point);
+        }
+        let [m, n] = self.matrix;
+        let x = point[0] - self.center[0];
+        let y = point[1] - self.center[1];
+        self.source.get([
+            self.center[0] + m[0] * x + m[1] * y + self.offset[0],
+            self.center[1] + n[0] * x + n[1] * y + self.offset[1],
+        ])
+    }
+}
+
+/// The noise described by `controls` with its seed, sampled on the plane given
+/// by `lift` and moved by the offset, rotation and shear of the controls. When
+/// `tile` is set the noise repeats over the scale of the noise, which is once
+/// across the image, but only while it isn't rotated or sheared.
+pub fn choose_noise(controls: &NoiseControls, lift: Lift, tile: bool) -> Affine<NoiseFunction> {
+    let period = tile.then_some([controls.scale_x as f64, controls.scale_y as f64]);
+    // A sine repeats across the image when it has a whole number of periods.
+    let snap = |freq: f32, scale: f32| {
+        if tile {
+            let p = scale as f64 / TAU;
+            (freq as f64 * p).round().max(1.0) / p
+        } else {
+            freq as f64
+        }
+    };
+    let seed = controls.seed;
+    let domain = |source: Dims| Domain::new(source, lift, period);
+    let fbm = || domain(AnyFbm::build(controls));
+    let nf = match controls.function.unwrap_or(NoiseFunctionName::Fbm) {
+        NoiseFunctionName::Fbm => NoiseFunction::Lifted(fbm()),
+        NoiseFunctionName::Billow => NoiseFunction::Lifted(domain(AnyBillow::build(controls))),
+        NoiseFunctionName::Ridged => NoiseFunction::Lifted(domain(AnyRidged::build(controls))),
+        NoiseFunctionName::HybridMulti => {
+            NoiseFunction::Lifted(domain(AnyHybridMulti::build(controls)))
+        }
+        NoiseFunctionName::BasicMulti => {
+            NoiseFunction::Lifted(domain(AnyBasicMulti::build(controls)))
+        }
+        NoiseFunctionName::Value => NoiseFunction::Lifted(domain(Dims::new(Value::new(seed)))),
+        NoiseFunctionName::OpenSimplex => {
+            NoiseFunction::Lifted(domain(Dims::new(OpenSimplex::new(seed))))
+        }
+        NoiseFunctionName::SuperSimplex => {
+            NoiseFunction::Lifted(domain(Dims::flat(SuperSimplex::new(seed))))
+        }
+        NoiseFunctionName::Worley => NoiseFunction::Lifted(domain(Dims::new(
+            Cells::new(
+                controls
+                    .worley_distance
+                    .unwrap_or(WorleyDistance::Euclidean),
+                controls.worley_return.unwrap_or(WorleyReturn::Value),
+            )
+            .set_seed(seed),
+        ))),
+        NoiseFunctionName::Checkerboard => NoiseFunction::Checkerboard(
+            Shift::new(
+                TranslatePoint::new(
+                    Checkerboard::default().set_size(controls.checker_size as usize),
+                )
+                .set_x_translation(lift.shift()[0])
+                .set_y_translation(lift.shift()[1]),
+                controls.scale_x.max(controls.scale_y) as f64,
+            )
+            .set_seed(seed),
         ),
-        NoiseFunctionName::Billow => NoiseFunction::Billow(
-            Billow::<Perlin>::default()
-                .set_octaves(controls.octaves as usize)
-                .set_frequency(controls.frequency as f64),
+        NoiseFunctionName::Cylinders => NoiseFunction::Cylinders(
+            Shift::new(
+                TranslatePoint::new(
+                    Cylinders::default().set_frequency(controls.octaves as f64 / 2.0),
+                )
+                .set_x_translation(lift.shift()[0])
+                .set_y_translation(lift.shift()[1]),
+                controls.scale_x.max(controls.scale_y) as f64,
+            )
+            .set_seed(seed),
         ),
-        NoiseFunctionName::Ridged => NoiseFunction::Ridged(
-            RidgedMulti::<Perlin>::default()
-                .set_octaves(controls.octaves as usize)
-                .set_frequency(controls.frequency as f64),
+        NoiseFunctionName::Curl => NoiseFunction::Curl(Curl::new(fbm())),
+        NoiseFunctionName::Sinusoidal => NoiseFunction::Sinusoidal(
+            Sinusoidal::new(
+                snap(controls.sin_x_freq, controls.scale_x),
+                snap(controls.sin_y_freq, controls.scale_y),
+            )
+            .set_phase(lift.phase())
+            .set_seed(seed),
         ),
-        NoiseFunctionName::Value => NoiseFunction::Value(Value::default()),
-        NoiseFunctionName::Cylinders => NoiseFunction::Cylinders(TranslatePoint::new(
-            Cylinders::default().set_frequency(controls.octaves as f64 / 2.0),
-        )),
-        NoiseFunctionName::Curl => {
-            let nf = Fbm::<Perlin>::default()
-                .set_octaves(controls.octaves as usize)
-                .set_frequency(controls.frequency as f64);
-            NoiseFunction::Curl(Curl::new(nf))
-        }
-        NoiseFunctionName::Sinusoidal => NoiseFunction::Sinusoidal(Sinusoidal::new(
-            controls.sin_x_freq as f64,
-            controls.sin_y_freq as f64,
-        )),
-        NoiseFunctionName::SinFbm => NoiseFunction::SinFbm(Sin::new(
-            Fbm::<Perlin>::default()
-                .set_octaves(controls.octaves as usize)
-                .set_frequency(controls.frequency as f64),
-        )),
+        NoiseFunctionName::SinFbm => NoiseFunction::SinFbm(Sin::new(fbm())),
         NoiseFunctionName::Image => NoiseFunction::Image(
-            ImgNoise::new(controls.img.clone()).set_map(controls.img_color_map.unwrap()),
+            Fold::new(
+                ImgNoise::new(controls.img.as_ref().clone())
+                    .set_map(controls.img_color_map.unwrap_or(ColorMap::Lightness)),
+                [controls.scale_x as f64, controls.scale_y as f64],
+            )
+            .set_seed(seed),
         ),
-    }
+    };
+    Affine::new(
+        nf,
+        [controls.scale_x as f64 / 2.0, controls.scale_y as f64 / 2.0],
+    )
+    .set_linear(
+        controls.rotation as f64,
+        controls.shear_x as f64,
+        controls.shear_y as f64,
+    )
+    .set_offset(controls.offset_x as f64, controls.offset_y as f64)
 }
 
 #[derive(Debug, Clone, Copy)]
 pub struct Sinusoidal {
     x_freq: f64,
     y_freq: f64,
+    phase: f64,
+    seed: u32,
+    seed_phase: [f64; 2],
 }
 
 impl Default for Sinusoidal {
@@ -417,19 +1464,47 @@ impl Default for Sinusoidal {
         Self {
             x_freq: 1.0,
             y_freq: 1.0,
+            phase: 0.0,
+            seed: 0,
+            seed_phase: [0.0; 2],
         }
     }
 }
 
 impl Sinusoidal {
     pub fn new(x_freq: f64, y_freq: f64) -> Self {
-        Self { x_freq, y_freq }
+        Self {
+            x_freq,
+            y_freq,
+            ..Default::default()
+        }
+    }
+
+    pub fn set_phase(self, phase: f64) -> Self {
+        Self { phase, ..self }
     }
 }
 
 impl NoiseFn<f64, 2> for Sinusoidal {
     fn get(&self, point: [f64; 2]) -> f64 {
-        0.5 * ((self.x_freq * point[0]).sin() + (self.y_freq * point[1]).sin())
+        let [u, v] = self.seed_phase;
+        0.5 * ((self.x_freq * point[0] + self.phase + u).sin()
+            + (self.y_freq * point[1] + self.phase + v).sin())
+    }
+}
+
+/// The seed picks the phase of each of the sines.
+impl Seedable for Sinusoidal {
+    fn set_seed(self, seed: u32) -> Self {
+        Self {
+            seed,
+            seed_phase: seed_unit(seed).map(|u| TAU * u),
+            ..self
+        }
+    }
+
+    fn seed(&self) -> u32 {
+        self.seed
     }
 }
 
@@ -463,3 +1538,178 @@ where
         (self.source.get(point) * std::f64::consts::PI).sin()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::animation::AnimationControls;
+
+    #[test]
+    fn loops_end_where_they_start() {
+        let animation = AnimationControls {
+            animate: true,
+            looping: true,
+            speed: 2.0,
+            ..Default::default()
+        };
+        let lift = |time: f32| {
+            AnimationControls {
+                time,
+                ..animation.clone()
+            }
+            .lift()
+        };
+        for function in [
+            NoiseFunctionName::Fbm,
+            NoiseFunctionName::Checkerboard,
+            NoiseFunctionName::Cylinders,
+            NoiseFunctionName::Sinusoidal,
+        ] {
+            let controls = NoiseControls {
+                function: Some(function),
+                ..Default::default()
+            };
+            let first = choose_noise(&controls, lift(0.0), false);
+            let last = choose_noise(&controls, lift(animation.duration()), false);
+            let middle = choose_noise(&controls, lift(animation.duration() / 2.0), false);
+            let points = (0..16).map(|i| [0.37 * i as f64 + 0.11, 0.53 * i as f64 + 0.23]);
+            let mut moved = false;
+            for p in points {
+                assert!((first.get(p) - last.get(p)).abs() < 1e-9, "{function:?}");
+                moved |= first.get(p) != middle.get(p);
+            }
+            assert!(moved, "{function:?}");
+        }
+    }
+
+    #[test]
+    fn tileable_noise_wraps() {
+        let controls = NoiseControls {
+            octaves: 4,
+            scale_x: 6.0,
+            scale_y: 3.0,
+            ..Default::default()
+        };
+        let (w, h) = (controls.scale_x as f64, controls.scale_y as f64);
+        let noise = choose_noise(&controls, Lift::Plane, true);
+        for i in 0..16 {
+            let (x, y) = (0.41 * i as f64, 0.19 * i as f64);
+            assert!((noise.get([0.0, y]) - noise.get([w, y])).abs() < 1e-9);
+            assert!((noise.get([x, 0.0]) - noise.get([x, h])).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn default_fractals_are_unchanged() {
+        for octaves in [1, 4] {
+            let controls = NoiseControls {
+                octaves,
+                seed: 7,
+                ..Default::default()
+            };
+            let before = Fbm::<Perlin>::default()
+                .set_octaves(octaves as usize)
+                .set_frequency(1.0)
+                .set_seed(7);
+            let after = AnyFbm::build(&controls);
+            for i in 0..16 {
+                let point = [0.37 * i as f64, -0.23 * i as f64];
+                assert!((before.get(point) - after.plane.get(point)).abs() < 1e-4);
+            }
+        }
+    }
+
+    #[test]
+    fn noises_report_their_seed() {
+        use NoiseFunctionName::*;
+        let functions = [
+            Fbm,
+            Billow,
+            Ridged,
+            HybridMulti,
+            BasicMulti,
+            Value,
+            OpenSimplex,
+            SuperSimplex,
+            Worley,
+            Checkerboard,
+            Cylinders,
+            Curl,
+            Sinusoidal,
+            SinFbm,
+            Image,
+        ];
+        for function in functions {
+            for base in [
+                BaseNoise::Perlin,
+                BaseNoise::SuperSimplex,
+                BaseNoise::Worley,
+            ] {
+                let controls = NoiseControls {
+                    function: Some(function),
+                    base: Some(base),
+                    seed: 11,
+                    ..Default::default()
+                };
+                let noise = choose_noise(&controls, Lift::Plane, false);
+                assert_eq!(noise.seed(), 11, "{function}");
+                assert_eq!(noise.set_seed(42).seed(), 42, "{function}");
+            }
+        }
+    }
+
+    /// A noise that gives away the point it was asked for.
+    struct Probe(usize);
+
+    impl NoiseFn<f64, 2> for Probe {
+        fn get(&self, point: [f64; 2]) -> f64 {
+            point[self.0]
+        }
+    }
+
+    #[test]
+    fn affine_round_trips() {
+        let center = [4.0, 3.0];
+        let point = [1.5, -2.0];
+        for (angle, shear_x, shear_y) in [(30.0, 0.0, 0.0), (-75.0, 0.4, 0.0), (0.0, 0.0, -0.7)] {
+            for axis in 0..2 {
+                let there = Affine::new(Probe(axis), center)
+                    .set_linear(angle, shear_x, shear_y)
+                    .set_offset(0.5, -1.0);
+                // Undone in the opposite order, the shear before the rotation.
+                let back = Affine::new(there, center).set_linear(0.0, -shear_x, 0.0);
+                let back = Affine::new(back, center).set_linear(0.0, 0.0, -shear_y);
+                let back = Affine::new(back, center).set_linear(-angle, 0.0, 0.0);
+                let back = Affine::new(back, center).set_offset(-0.5, 1.0);
+                assert!(
+                    (back.get(point) - point[axis]).abs() < 1e-9,
+                    "{angle} {axis}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn affine_turns_about_the_center() {
+        let center = [4.0, 3.0];
+        let turn = |axis| Affine::new(Probe(axis), center).set_linear(90.0, 0.0, 0.0);
+        let point = [5.0, 3.0];
+        assert!((turn(0).get(point) - 4.0).abs() < 1e-9);
+        assert!((turn(1).get(point) - 4.0).abs() < 1e-9);
+        assert!(Affine::new(Probe(0), center).is_identity());
+    }
+
+    #[test]
+    fn switching_keeps_a_changed_persistence() {
+        use NoiseFunctionName::*;
+        let mut controls = NoiseControls::default();
+        controls.update(NoiseMessage::Function(Ridged));
+        assert_eq!(controls.persistence, 1.0);
+        controls.update(NoiseMessage::Function(Sinusoidal));
+        controls.update(NoiseMessage::Function(HybridMulti));
+        assert_eq!(controls.persistence, 0.25);
+        controls.update(NoiseMessage::Persistence(0.7));
+        controls.update(NoiseMessage::Function(Fbm));
+        assert_eq!(controls.persistence, 0.7);
+    }
+}
@@ -0,0 +1,430 @@
+use std::ops::Range;
+use std::sync::Arc;
+
+use rand::rngs::SmallRng;
+use rand::{Rng, SeedableRng};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use wassily::prelude::{
+    img_noise, noise2d, noise2d_01, pt, Color, Colorful, DynamicImage, NoiseOpts, Point,
+};
+
+use crate::noise::{choose_noise, Lift};
+use crate::sample::{parse_hex, Edge, Interpolation, Sampler};
+use crate::stage::WarpStage;
+use crate::{Controls, Coordinates};
+
+/// Adaptive sampling refines a pixel when any channel of its first samples
+/// differs by more than this.
+const ADAPTIVE_THRESHOLD: f32 = 0.1;
+
+/// Number of warped samples averaged for each output pixel.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Quality {
+    Single,
+    Grid2,
+    Grid4,
+    Adaptive,
+}
+
+impl std::fmt::Display for Quality {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Quality::Single => "1x",
+                Quality::Grid2 => "2x2",
+                Quality::Grid4 => "4x4",
+                Quality::Adaptive => "Adaptive",
+            }
+        )
+    }
+}
+
+impl Quality {
+    /// The most octaves a fractal noise may have. Every octave is another read
+    /// of the base noise, and supersampling reads the noise several times for
+    /// each pixel, so only the single sample gets the full range.
+    pub fn max_octaves(&self) -> i32 {
+        match self {
+            Quality::Single => 12,
+            _ => 6,
+        }
+    }
+}
+
+/// A displacement field, evaluated at a point of the output.
+type Field = Arc<dyn Fn(Point) -> Point + Send + Sync>;
+
+/// One level of a warp: the field and how its value moves a point.
+struct Stage {
+    field: Field,
+    coordinates: Coordinates,
+    center: Option<Point>,
+}
+
+impl Stage {
+    fn new(field: Field, coordinates: Coordinates, center: Option<Point>) -> Self {
+        Self {
+            field,
+            coordinates,
+            center,
+        }
+    }
+
+    /// The point that `p` reads its color from, the same as wassily's `Warp`.
+    /// A polar field is an angle and a radius, with a center the angle is
+    /// measured from the direction of `p` as seen from the center. A cartesian
+    /// field is an offset and an absolute field is the point itself.
+    fn warp(&self, p: Point) -> Point {
+        let q = (self.field)(p);
+        match self.coordinates {
+            Coordinates::Polar => {
+                let theta = match self.center {
+                    Some(c) => (p.y - c.y).atan2(p.x - c.x) + q.x,
+                    None => q.x,
+                };
+                pt(p.x + q.y * theta.cos(), p.y + q.y * theta.sin())
+            }
+            Coordinates::Cartesian => pt(p.x + q.x, p.y + q.y),
+            Coordinates::Absolute => q,
+        }
+    }
+}
+
+/// The displacement field of one stage of the warp of `img`, which is the
+/// source image downscaled by `scale`. Noise is sampled relative to the size of
+/// the image so only the displacements, which are measured in pixels of the
+/// full resolution source, need to be scaled. The angle of a polar warp does
+/// not depend on the resolution. The noise is sampled on the plane given by
+/// `lift`, and repeats across the image when `tile` is set.
+fn field(stage: &WarpStage, img: &DynamicImage, scale: f32, lift: Lift, tile: bool) -> Field {
+    let polar = stage.coordinates == Some(Coordinates::Polar);
+    let opts_theta = NoiseOpts::with_wh(img.width(), img.height())
+        .factor(stage.theta_noise.factor * if polar { 1.0 } else { scale })
+        .y_scale(stage.theta_noise.scale_y)
+        .x_scale(stage.theta_noise.scale_x);
+    let nf_theta = choose_noise(&stage.theta_noise, lift, tile);
+    let opts_r = if stage.sync {
+        let factor = if polar {
+            30.0 * stage.theta_noise.factor * scale
+        } else {
+            opts_theta.factor
+        };
+        opts_theta.factor(factor)
+    } else {
+        NoiseOpts::with_wh(img.width(), img.height())
+            .factor(stage.radius_noise.factor * scale)
+            .y_scale(stage.radius_noise.scale_y)
+            .x_scale(stage.radius_noise.scale_x)
+    };
+    // In sync the second channel is the same noise with the next seed.
+    let nf_r = if stage.sync {
+        let mut tn = stage.theta_noise.clone();
+        tn.img_color_map = Some(img_noise::ColorMap::RedGreen);
+        tn.seed = tn.seed.wrapping_add(1);
+        choose_noise(&tn, lift, tile)
+    } else {
+        choose_noise(&stage.radius_noise, lift, tile)
+    };
+
+    match stage.coordinates.unwrap_or(Coordinates::Cartesian) {
+        Coordinates::Polar => Arc::new(move |z: Point| {
+            pt(
+                noise2d(&nf_theta, &opts_theta, z.x, z.y),
+                noise2d_01(&nf_r, &opts_r, z.x, z.y),
+            )
+        }),
+        Coordinates::Cartesian | Coordinates::Absolute => Arc::new(move |z: Point| {
+            pt(
+                noise2d(&nf_theta, &opts_theta, z.x, z.y),
+                noise2d(&nf_r, &opts_r, z.x, z.y),
+            )
+        }),
+    }
+}
+
+/// The warp described by a set of `Controls`, ready to be evaluated over any
+/// band of rows of a `width` x `height` output.
+pub struct Render<'a> {
+    stages: Vec<Stage>,
+    sampler: Sampler<'a>,
+    mask: Option<Sampler<'a>>,
+    mx: f32,
+    my: f32,
+    quality: Quality,
+    width: u32,
+    sx: f32,
+    sy: f32,
+}
+
+impl<'a> Render<'a> {
+    /// Warp `img`, which is the source image downscaled by `scale`, into a
+    /// `width` x `height` output. Each output pixel samples the warp at the
+    /// matching point of `img`, so rendering larger than `img` samples the
+    /// source at the finer density instead of resizing a rendered image.
+    pub fn new(
+        controls: &'a Controls,
+        img: &'a DynamicImage,
+        scale: f32,
+        width: u32,
+        height: u32,
+    ) -> Self {
+        // A point of the output passes through the last stage first.
+        let lift = controls.animation.lift();
+        let center = controls.centered.then(|| {
+            pt(
+                controls.center_x * img.width() as f32,
+                controls.center_y * img.height() as f32,
+            )
+        });
+        let stages = controls
+            .stages
+            .iter()
+            .rev()
+            .filter(|stage| !stage.muted)
+            .map(|stage| {
+                Stage::new(
+                    field(stage, img, scale, lift, controls.tileable),
+                    stage.coordinates.unwrap_or(Coordinates::Cartesian),
+                    center,
+                )
+            })
+            .collect();
+        // The mask is stretched over the image whatever its size.
+        let (mx, my) = controls.mask.as_ref().map_or((1.0, 1.0), |mask| {
+            (
+                mask.width() as f32 / img.width() as f32,
+                mask.height() as f32 / img.height() as f32,
+            )
+        });
+        Self {
+            stages,
+            mask: controls
+                .mask
+                .as_deref()
+                .map(|mask| Sampler::new(mask, Interpolation::Bilinear, Edge::Clamp, Color::BLACK)),
+            mx,
+            my,
+            sampler: Sampler::new(
+                img,
+                controls.interpolation.unwrap_or(Interpolation::Bilinear),
+                controls.edge.unwrap_or(Edge::Wrap),
+                parse_hex(&controls.edge_color).unwrap_or(Color::BLACK),
+            ),
+            quality: controls.quality.unwrap_or(Quality::Single),
+            width,
+            sx: img.width() as f32 / width as f32,
+            sy: img.height() as f32 / height as f32,
+        }
+    }
+
+    /// Render the RGBA pixels of `rows`, before any color adjustments.
+    /// Rendering gives up and returns `None` as soon as `keep_going` returns
+    /// false.
+    pub fn rows(&self, rows: Range<u32>, keep_going: impl Fn() -> bool + Sync) -> Option<Vec<u8>> {
+        let rows: Option<Vec<Vec<u8>>> = rows
+            .into_par_iter()
+            .map(|i| {
+                if !keep_going() {
+                    return None;
+                }
+                let mut row = Vec::with_capacity(4 * self.width as usize);
+                for j in 0..self.width {
+                    let t = self.pixel(j, i).as_u8s();
+                    row.extend([t.0, t.1, t.2, t.3]);
+                }
+                Some(row)
+            })
+            .collect();
+        rows.map(|rows| rows.concat())
+    }
+
+    /// How far each pixel of `rows` moves through the whole warp, in pixels of
+    /// the image being warped. Gives up like `rows` does.
+    pub fn displacements(
+        &self,
+        rows: Range<u32>,
+        keep_going: impl Fn() -> bool + Sync,
+    ) -> Option<Vec<Point>> {
+        let rows: Option<Vec<Vec<Point>>> = rows
+            .into_par_iter()
+            .map(|i| {
+                if !keep_going() {
+                    return None;
+                }
+                let row = (0..self.width)
+                    .map(|j| {
+                        let p0 = pt(j as f32 * self.sx, i as f32 * self.sy);
+                        let p = self.source_point(j as f32, i as f32);
+                        pt(p.x - p0.x, p.y - p0.y)
+                    })
+                    .collect();
+                Some(row)
+            })
+            .collect();
+        rows.map(|rows| rows.concat())
+    }
+
+    /// The RGBA pixels of `rows` of the source itself, without the warp,
+    /// matching the output pixel for pixel.
+    pub fn source(&self, rows: Range<u32>) -> Vec<u8> {
+        rows.into_par_iter()
+            .flat_map_iter(|i| {
+                (0..self.width).flat_map(move |j| {
+                    let t = self
+                        .sampler
+                        .get(j as f32 * self.sx, i as f32 * self.sy)
+                        .as_u8s();
+                    [t.0, t.1, t.2, t.3]
+                })
+            })
+            .collect()
+    }
+
+    /// The color of the output pixel at `x`, `y`, averaged over the samples
+    /// the quality setting asks for.
+    fn pixel(&self, x: u32, y: u32) -> Color {
+        match self.quality {
+            Quality::Single => self.sample(x as f32, y as f32),
+            Quality::Grid2 => average(&self.grid(x, y, 2, None)),
+            Quality::Grid4 => average(&self.grid(x, y, 4, None)),
+            Quality::Adaptive => {
+                // Jitter is seeded by the pixel so repeated renders agree.
+                let mut rng = SmallRng::seed_from_u64(((y as u64) << 32) | x as u64);
+                let mut samples = self.grid(x, y, 2, Some(&mut rng));
+                if spread(&samples) > ADAPTIVE_THRESHOLD {
+                    samples.extend(self.grid(x, y, 4, Some(&mut rng)));
+                }
+                average(&samples)
+            }
+        }
+    }
+
+    /// Sample an `n` x `n` grid of points covering the pixel at `x`, `y`,
+    /// jittered within each cell when given a random number generator.
+    fn grid(&self, x: u32, y: u32, n: u32, mut rng: Option<&mut SmallRng>) -> Vec<Color> {
+        let mut samples = Vec::with_capacity((n * n) as usize);
+        for k in 0..n {
+            for l in 0..n {
+                let (dx, dy) = match rng.as_mut() {
+                    Some(rng) => (rng.gen::<f32>(), rng.gen::<f32>()),
+                    None => (0.5, 0.5),
+                };
+                samples.push(self.sample(
+                    x as f32 + (l as f32 + dx) / n as f32 - 0.5,
+                    y as f32 + (k as f32 + dy) / n as f32 - 0.5,
+                ));
+            }
+        }
+        samples
+    }
+
+    /// Follow the point `x`, `y` of the output through every stage of the warp
+    /// and read the source where it lands.
+    fn sample(&self, x: f32, y: f32) -> Color {
+        let p = self.source_point(x, y);
+        self.sampler.get(p.x, p.y)
+    }
+
+    /// The point of the image that the point `x`, `y` of the output reads
+    /// from. The displacement of the whole chain is scaled by the luminance of
+    /// the mask, black leaves the point in place.
+    fn source_point(&self, x: f32, y: f32) -> Point {
+        let p0 = pt(x * self.sx, y * self.sy);
+        let mut p = self.stages.iter().fold(p0, |p, stage| stage.warp(p));
+        if let Some(mask) = &self.mask {
+            let c = mask.get(p0.x * self.mx, p0.y * self.my);
+            let m = (0.2126 * c.red() + 0.7152 * c.green() + 0.0722 * c.blue()) * c.alpha();
+            p = pt(p0.x + m * (p.x - p0.x), p0.y + m * (p.y - p0.y));
+        }
+        p
+    }
+}
+
+/// Render the whole `width` x `height` warp of `img` in one go.
+pub fn draw(
+    controls: &Controls,
+    img: &DynamicImage,
+    scale: f32,
+    width: u32,
+    height: u32,
+    keep_going: impl Fn() -> bool + Sync,
+) -> Option<Vec<u8>> {
+    Render::new(controls, img, scale, width, height).rows(0..height, keep_going)
+}
+
+/// The mean of `colors`, weighting each by its alpha so transparent samples
+/// don't bleed their color into the result.
+fn average(colors: &[Color]) -> Color {
+    let (mut r, mut g, mut b, mut a) = (0.0, 0.0, 0.0, 0.0);
+    for c in colors {
+        r += c.red() * c.alpha();
+        g += c.green() * c.alpha();
+        b += c.blue() * c.alpha();
+        a += c.alpha();
+    }
+    if a == 0.0 {
+        return Color::TRANSPARENT;
+    }
+    Color::from_rgba(r / a, g / a, b / a, a / colors.len() as f32).unwrap()
+}
+
+/// The largest difference between any two samples in a single channel.
+fn spread(colors: &[Color]) -> f32 {
+    let channels: [fn(&Color) -> f32; 4] = [Color::red, Color::green, Color::blue, Color::alpha];
+    channels
+        .iter()
+        .map(|channel| {
+            let (lo, hi) = colors.iter().fold((1.0f32, 0.0f32), |(lo, hi), c| {
+                (lo.min(channel(c)), hi.max(channel(c)))
+            });
+            hi - lo
+        })
+        .fold(0.0, f32::max)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wassily::prelude::{Coord, ImageBuffer, Rgba, Warp, WarpNode};
+
+    /// An image whose color at each pixel is its coordinates.
+    fn coordinates_img() -> DynamicImage {
+        DynamicImage::ImageRgba8(ImageBuffer::from_fn(64, 64, |x, y| {
+            Rgba([4 * x as u8, 4 * y as u8, 0, 255])
+        }))
+    }
+
+    #[test]
+    fn stages_move_points_like_wassily() {
+        let img = coordinates_img();
+        let (w, h) = (img.width() as f32, img.height() as f32);
+        let sampler = Sampler::new(&img, Interpolation::Nearest, Edge::Wrap, Color::BLACK);
+        // Fields that land on whole pixels, so the way each samples doesn't
+        // matter.
+        let cases = [
+            (Coordinates::Polar, Coord::Polar, pt(0.0, 4.0)),
+            (Coordinates::Polar, Coord::Polar, pt(0.0, -3.0)),
+            (Coordinates::Cartesian, Coord::Cartesian, pt(3.0, 5.0)),
+            (Coordinates::Absolute, Coord::Absolute, pt(7.0, 9.0)),
+        ];
+        for (coordinates, coord, q) in cases {
+            let stage = Stage::new(Arc::new(move |_: Point| q), coordinates, None);
+            let warp = Warp::new(
+                Arc::new(move |_: Point| q),
+                WarpNode::Img(&img, w, h),
+                coord,
+            );
+            for (x, y) in [(10.0, 20.0), (30.0, 40.0), (50.0, 12.0)] {
+                let p = stage.warp(pt(x, y));
+                assert_eq!(
+                    sampler.get(p.x, p.y).as_u8s(),
+                    warp.get_wrapped(x, y).as_u8s(),
+                    "{coordinates:?} at ({x}, {y})"
+                );
+            }
+        }
+    }
+}
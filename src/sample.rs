@@ -0,0 +1,285 @@
+use std::borrow::Cow;
+use std::f32::consts::PI;
+
+use serde::{Deserialize, Serialize};
+use wassily::prelude::{Color, DynamicImage, ImageBuffer, Rgba};
+
+/// How the color between the pixels of the source is reconstructed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Interpolation {
+    Nearest,
+    Bilinear,
+    Bicubic,
+    Lanczos,
+}
+
+impl std::fmt::Display for Interpolation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Interpolation::Nearest => "Nearest",
+                Interpolation::Bilinear => "Bilinear",
+                Interpolation::Bicubic => "Bicubic",
+                Interpolation::Lanczos => "Lanczos",
+            }
+        )
+    }
+}
+
+/// What is read for points that fall outside of the image.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Edge {
+    Wrap,
+    Clamp,
+    Mirror,
+    Transparent,
+    Color,
+}
+
+impl std::fmt::Display for Edge {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Edge::Wrap => "Wrap",
+                Edge::Clamp => "Clamp",
+                Edge::Mirror => "Mirror",
+                Edge::Transparent => "Transparent",
+                Edge::Color => "Color",
+            }
+        )
+    }
+}
+
+/// Parse a color written as `#rrggbb` or `#rrggbbaa`, the `#` is optional.
+pub fn parse_hex(hex: &str) -> Option<Color> {
+    let hex = hex.trim().trim_start_matches('#');
+    if !(hex.len() == 6 || hex.len() == 8) || !hex.is_ascii() {
+        return None;
+    }
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+    let a = if hex.len() == 8 { channel(6)? } else { 255 };
+    Some(Color::from_rgba8(channel(0)?, channel(2)?, channel(4)?, a))
+}
+
+/// Reads the color of an image at fractional pixel coordinates. Pixel centers
+/// sit at integer coordinates.
+pub struct Sampler<'a> {
+    img: Cow<'a, ImageBuffer<Rgba<u8>, Vec<u8>>>,
+    interpolation: Interpolation,
+    edge: Edge,
+    /// Premultiplied color of the points outside of the image for the
+    /// `Transparent` and `Color` edges.
+    outside: [f32; 4],
+}
+
+impl<'a> Sampler<'a> {
+    /// Points outside of the image are handled according to `edge`, with
+    /// `background` used by `Edge::Color`.
+    pub fn new(
+        img: &'a DynamicImage,
+        interpolation: Interpolation,
+        edge: Edge,
+        background: Color,
+    ) -> Self {
+        let img = match img.as_rgba8() {
+            Some(rgba) => Cow::Borrowed(rgba),
+            None => Cow::Owned(img.to_rgba8()),
+        };
+        let outside = match edge {
+            Edge::Color => [
+                background.red() * background.alpha(),
+                background.green() * background.alpha(),
+                background.blue() * background.alpha(),
+                background.alpha(),
+            ],
+            _ => [0.0; 4],
+        };
+        Self {
+            img,
+            interpolation,
+            edge,
+            outside,
+        }
+    }
+
+    pub fn get(&self, x: f32, y: f32) -> Color {
+        let c = match self.interpolation {
+            Interpolation::Nearest => self.texel(x.round() as i64, y.round() as i64),
+            Interpolation::Bilinear => self.filter(x, y, 1, triangle),
+            Interpolation::Bicubic => self.filter(x, y, 2, catmull_rom),
+            Interpolation::Lanczos => self.filter(x, y, 3, lanczos3),
+        };
+        unpremultiply(c)
+    }
+
+    /// The premultiplied color of the pixel at `x`, `y`.
+    fn texel(&self, x: i64, y: i64) -> [f32; 4] {
+        let (w, h) = (self.img.width() as i64, self.img.height() as i64);
+        let (x, y) = match self.edge {
+            Edge::Wrap => (x.rem_euclid(w), y.rem_euclid(h)),
+            Edge::Clamp => (x.clamp(0, w - 1), y.clamp(0, h - 1)),
+            Edge::Mirror => (mirror(x, w), mirror(y, h)),
+            Edge::Transparent | Edge::Color => {
+                if x < 0 || y < 0 || x >= w || y >= h {
+                    return self.outside;
+                }
+                (x, y)
+            }
+        };
+        let p = self.img.get_pixel(x as u32, y as u32).0;
+        let a = p[3] as f32 / 255.0;
+        [
+            p[0] as f32 / 255.0 * a,
+            p[1] as f32 / 255.0 * a,
+            p[2] as f32 / 255.0 * a,
+            a,
+        ]
+    }
+
+    /// Weigh the pixels within `radius` of `x`, `y` with a separable kernel.
+    fn filter(&self, x: f32, y: f32, radius: i64, kernel: fn(f32) -> f32) -> [f32; 4] {
+        let (x0, y0) = (x.floor() as i64, y.floor() as i64);
+        let mut acc = [0.0; 4];
+        let mut total = 0.0;
+        for j in y0 - radius + 1..=y0 + radius {
+            let wy = kernel(y - j as f32);
+            for i in x0 - radius + 1..=x0 + radius {
+                let w = wy * kernel(x - i as f32);
+                for (acc, t) in acc.iter_mut().zip(self.texel(i, j)) {
+                    *acc += w * t;
+                }
+                total += w;
+            }
+        }
+        acc.map(|c| c / total)
+    }
+}
+
+/// Reflect `x` back into `0..n`, repeating the edge pixel at each fold.
+fn mirror(x: i64, n: i64) -> i64 {
+    let x = x.rem_euclid(2 * n);
+    if x < n {
+        x
+    } else {
+        2 * n - 1 - x
+    }
+}
+
+fn unpremultiply([r, g, b, a]: [f32; 4]) -> Color {
+    // Negative lobes of the bicubic and Lanczos kernels can overshoot.
+    let a = a.clamp(0.0, 1.0);
+    if a == 0.0 {
+        return Color::TRANSPARENT;
+    }
+    Color::from_rgba(
+        (r / a).clamp(0.0, 1.0),
+        (g / a).clamp(0.0, 1.0),
+        (b / a).clamp(0.0, 1.0),
+        a,
+    )
+    .unwrap()
+}
+
+fn triangle(t: f32) -> f32 {
+    (1.0 - t.abs()).max(0.0)
+}
+
+fn catmull_rom(t: f32) -> f32 {
+    let t = t.abs();
+    if t < 1.0 {
+        1.5 * t * t * t - 2.5 * t * t + 1.0
+    } else if t < 2.0 {
+        -0.5 * t * t * t + 2.5 * t * t - 4.0 * t + 2.0
+    } else {
+        0.0
+    }
+}
+
+fn sinc(t: f32) -> f32 {
+    if t == 0.0 {
+        1.0
+    } else {
+        (PI * t).sin() / (PI * t)
+    }
+}
+
+fn lanczos3(t: f32) -> f32 {
+    if t.abs() < 3.0 {
+        sinc(t) * sinc(t / 3.0)
+    } else {
+        0.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn kernels_sum_to_one() {
+        let kernels: [(i64, fn(f32) -> f32, f32); 3] = [
+            (1, triangle, 1e-5),
+            (2, catmull_rom, 1e-5),
+            (3, lanczos3, 0.01),
+        ];
+        for (radius, kernel, tolerance) in kernels {
+            for k in 0..10 {
+                let t = k as f32 / 10.0;
+                let sum: f32 = (1 - radius..=radius).map(|i| kernel(t - i as f32)).sum();
+                assert!(
+                    (sum - 1.0).abs() < tolerance,
+                    "radius {radius} at {t}: {sum}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn parses_hex_colors() {
+        assert_eq!(
+            parse_hex("#ff8000"),
+            Some(Color::from_rgba8(255, 128, 0, 255))
+        );
+        assert_eq!(
+            parse_hex(" 00ff0080 "),
+            Some(Color::from_rgba8(0, 255, 0, 128))
+        );
+        assert_eq!(parse_hex("#fff"), None);
+        assert_eq!(parse_hex("#gg0000"), None);
+        assert_eq!(parse_hex("#ff00é"), None);
+    }
+
+    #[test]
+    fn mirror_repeats_the_edge_pixel() {
+        let folded: Vec<i64> = (-4..8).map(|x| mirror(x, 3)).collect();
+        assert_eq!(folded, [2, 2, 1, 0, 0, 1, 2, 2, 1, 0, 0, 1]);
+    }
+
+    #[test]
+    fn edges_pick_the_pixel_read_outside() {
+        // The red of each pixel is its index.
+        let img = DynamicImage::ImageRgba8(ImageBuffer::from_fn(3, 1, |x, _| {
+            Rgba([x as u8, 0, 0, 255])
+        }));
+        let index = |edge: Edge, x: i64| {
+            let texel = Sampler::new(&img, Interpolation::Nearest, edge, Color::BLACK).texel(x, 0);
+            (texel[0] * 255.0).round() as i64
+        };
+        let read = |edge: Edge| -> Vec<i64> { (-2..5).map(|x| index(edge, x)).collect() };
+        assert_eq!(read(Edge::Wrap), [1, 2, 0, 1, 2, 0, 1]);
+        assert_eq!(read(Edge::Clamp), [0, 0, 0, 1, 2, 2, 2]);
+        assert_eq!(read(Edge::Mirror), [1, 0, 0, 1, 2, 2, 1]);
+        let outside = Sampler::new(
+            &img,
+            Interpolation::Nearest,
+            Edge::Transparent,
+            Color::BLACK,
+        );
+        assert_eq!(outside.texel(-1, 0), [0.0; 4]);
+        assert_eq!(outside.texel(3, 0), [0.0; 4]);
+    }
+}
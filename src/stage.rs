@@ -0,0 +1,137 @@
+use iced::widget::{button, column, radio, text, toggler, Column, Container, Row, Rule};
+use iced::Element;
+use serde::{Deserialize, Serialize};
+
+use crate::noise::{NoiseControls, NoiseMessage};
+use crate::Coordinates;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum StageMessage {
+    Angle(NoiseMessage),
+    Radius(NoiseMessage),
+    CoordinatesMessage(Coordinates),
+    Sync(bool),
+    Mute(bool),
+    MoveUp,
+    MoveDown,
+    Remove,
+}
+
+/// One level of the warp chain. The first stage warps the source image and
+/// every following stage warps the result of the one before it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WarpStage {
+    pub theta_noise: NoiseControls,
+    pub radius_noise: NoiseControls,
+    pub coordinates: Option<Coordinates>,
+    pub sync: bool,
+    pub muted: bool,
+    #[serde(skip)]
+    pub dirty: bool,
+}
+
+impl Default for WarpStage {
+    fn default() -> Self {
+        Self {
+            theta_noise: NoiseControls {
+                factor: 500.0,
+                ..Default::default()
+            },
+            radius_noise: NoiseControls {
+                factor: 1000.0,
+                seed: 98713,
+                ..Default::default()
+            },
+            coordinates: Some(Coordinates::Cartesian),
+            sync: true,
+            muted: false,
+            dirty: false,
+        }
+    }
+}
+
+impl<'a> WarpStage {
+    /// Moving and removing stages changes the whole chain, those messages are
+    /// handled by the owner of the stages.
+    pub fn update(&mut self, message: StageMessage) {
+        use StageMessage::*;
+        self.dirty = true;
+        match message {
+            Angle(a) => {
+                self.theta_noise.update(a);
+                self.dirty = self.theta_noise.dirty;
+            }
+            Radius(r) => {
+                self.radius_noise.update(r);
+                self.dirty = self.radius_noise.dirty;
+            }
+            CoordinatesMessage(c) => self.coordinates = Some(c),
+            Sync(b) => self.sync = b,
+            Mute(b) => self.muted = b,
+            MoveUp | MoveDown | Remove => self.dirty = false,
+        }
+    }
+
+    /// The controls of the stage at `index` in a chain of `count` stages, with
+    /// fractal noises of up to `max_octaves`.
+    pub fn view(&self, index: usize, count: usize, max_octaves: i32) -> Element<'a, StageMessage> {
+        use StageMessage::*;
+        let polar = self.coordinates == Some(Coordinates::Polar);
+        let mut buttons = Row::new().spacing(5);
+        if index > 0 {
+            buttons = buttons.push(button(text("Up").size(15)).on_press(MoveUp));
+        }
+        if index + 1 < count {
+            buttons = buttons.push(button(text("Down").size(15)).on_press(MoveDown));
+        }
+        if count > 1 {
+            buttons = buttons.push(button(text("Remove").size(15)).on_press(Remove));
+        }
+        let mut col = Column::new()
+            .push(Rule::horizontal(5))
+            .push(text(format!("Stage {}", index + 1)))
+            .push(buttons)
+            .push(Container::new(
+                toggler("Mute".to_owned(), self.muted, Mute).text_size(15),
+            ))
+            .push(
+                column(
+                    [
+                        Coordinates::Polar,
+                        Coordinates::Cartesian,
+                        Coordinates::Absolute,
+                    ]
+                    .iter()
+                    .cloned()
+                    .map(|d| {
+                        radio(d, d, self.coordinates, CoordinatesMessage)
+                            .text_size(15)
+                            .size(15)
+                    })
+                    .map(Element::from)
+                    .collect(),
+                )
+                .spacing(15),
+            )
+            .push(Container::new(
+                toggler("Sync".to_owned(), self.sync, Sync).text_size(15),
+            ))
+            .push(if polar {
+                text("Angle")
+            } else {
+                text("X Coordinate")
+            })
+            .push(self.theta_noise.view(max_octaves).map(Angle));
+        if !self.sync {
+            col = col
+                .push(if polar {
+                    text("Radius")
+                } else {
+                    text("Y Coordinate")
+                })
+                .push(self.radius_noise.view(max_octaves).map(Radius));
+        }
+        col.spacing(15).into()
+    }
+}